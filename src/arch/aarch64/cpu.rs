@@ -1,7 +1,12 @@
 use core::arch::asm;
 
-pub use bare_metal::{CriticalSection, Mutex};
 use cortex_a::asm;
+use cortex_a::asm::barrier;
+use cortex_a::registers::{DAIF, MPIDR_EL1};
+use tock_registers::interfaces::{Readable, Writeable};
+
+/// Mask for the core number in MPIDR_EL1.Aff0.
+const CORE_ID_MASK: u64 = 0b11;
 
 /// Stop execution on core.
 #[inline(always)]
@@ -11,35 +16,66 @@ pub fn wait_forever() -> ! {
   }
 }
 
+/// The ID of the executing core.
 #[inline(always)]
-pub fn disable_interrupts() {
-  #[cfg(target_arch = "aarch64")]
-  unsafe {
-    asm!("msr daifset, #15", options(nomem, nostack, preserves_flags))
-  }
+pub fn core_id() -> usize {
+  (MPIDR_EL1.get() & CORE_ID_MASK) as usize
+}
+
+/// Wake up all cores waiting in `wait_for_event`.
+#[inline(always)]
+pub fn send_event() {
+  unsafe { barrier::dsb(barrier::SY) };
+  asm::sev()
+}
+
+/// Sleep until an event is signalled by another core.
+#[inline(always)]
+pub fn wait_for_event() {
+  asm::wfe()
+}
 
-  #[cfg(not(target_arch = "aarch64"))]
-  unimplemented!()
+/// Sleep until an interrupt is pending. Also wakes up for interrupts that are
+/// masked on this core.
+#[inline(always)]
+pub fn wait_for_interrupt() {
+  asm::wfi()
 }
 
+/// Clean the data cache line holding `addr` to the point of coherency, so
+/// that observers with caches off see it.
 #[inline(always)]
-pub fn enable_interrupts() {
-  #[cfg(target_arch = "aarch64")]
+pub fn clean_dcache_line(addr: usize) {
   unsafe {
-    asm!("msr daifset, #0", options(nomem, nostack, preserves_flags))
+    asm!("dc cvac, {}", in(reg) addr, options(nostack));
+    barrier::dsb(barrier::SY);
   }
+}
+
+/// Wait until all memory accesses so far are complete, e.g. before telling a
+/// device to read a buffer, or after it wrote one.
+#[inline(always)]
+pub fn memory_barrier() {
+  unsafe { barrier::dsb(barrier::SY) };
+}
 
-  #[cfg(not(target_arch = "aarch64"))]
-  unimplemented!()
+/// Mask IRQ and FIQ on the executing core. Returns the previous state for
+/// `local_irq_restore`.
+#[inline(always)]
+pub fn local_irq_mask_save() -> u64 {
+  let saved = DAIF.get();
+  unsafe { asm!("msr daifset, #3", options(nostack, preserves_flags)) };
+  saved
 }
 
-#[inline]
-pub fn free<F, R>(f: F) -> R
-where
-  F: FnOnce(&CriticalSection) -> R,
-{
-  disable_interrupts();
-  let r = f(unsafe { &CriticalSection::new() });
-  enable_interrupts();
-  r
+/// Unmask IRQ and FIQ on the executing core.
+#[inline(always)]
+pub fn local_irq_unmask() {
+  unsafe { asm!("msr daifclr, #3", options(nostack, preserves_flags)) };
+}
+
+/// Restore an interrupt mask state saved by `local_irq_mask_save`.
+#[inline(always)]
+pub fn local_irq_restore(saved: u64) {
+  DAIF.set(saved)
 }
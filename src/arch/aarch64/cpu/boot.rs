@@ -1,6 +1,13 @@
 core::arch::global_asm!(include_str!("boot.s"));
 
+/// Entry of the boot core. The firmware passes the device tree address.
 #[no_mangle]
-pub unsafe fn _start_rust() -> ! {
-  crate::kernel_main()
+pub unsafe extern "C" fn _start_rust(dtb_addr: u64) -> ! {
+  crate::kernel_main(dtb_addr as usize)
+}
+
+/// Entry of cores 1-3 after they have been released by the boot core.
+#[no_mangle]
+pub unsafe extern "C" fn _start_rust_secondary(core_id: u64) -> ! {
+  crate::kernel_secondary_main(core_id as usize)
 }
@@ -20,6 +20,109 @@
 
 .equ _core_id_mask, 0b11
 
+// CurrentEL values.
+.equ _el1, 0b0100
+.equ _el2, 0b1000
+.equ _el3, 0b1100
+
+// SCR_EL3: Non-secure, RES1 bits, SMC disabled, HVC enabled, lower levels are AArch64.
+.equ _scr_el3_value, (1 << 10) | (1 << 8) | (1 << 7) | (1 << 5) | (1 << 4) | (1 << 0)
+
+// HCR_EL2: EL1 executes in AArch64.
+.equ _hcr_el2_value, (1 << 31)
+
+// CNTHCTL_EL2: EL1 may access the physical counter and timer registers.
+.equ _cnthctl_el2_el1_access, 0b11
+
+// SPSR_ELx: Debug, SError, IRQ and FIQ masked, return to EL2h or EL1t respectively. The kernel runs
+// on SP_EL0, exceptions are taken on SP_EL1.
+.equ _spsr_el2h_masked, 0b1111001001
+.equ _spsr_el1t_masked, 0b1111000100
+
+// SCTLR_EL1: Only the RES1 bits set. MMU and caches off, little endian.
+.equ _sctlr_el1_value, 0x30D00800
+
+// CPACR_EL1: FP/SIMD instructions don't trap at EL1 and EL0.
+.equ _cpacr_el1_fpen, (0b11 << 20)
+
+// CPTR_EL2: Only the RES1 bits set. FP/SIMD instructions don't trap to EL2.
+.equ _cptr_el2_value, 0x33FF
+
+// Load the end of the executing core's exception stack into `register`. Clobbers x10 and x11.
+.macro EXCEPTION_STACK_END register
+	mrs	x10, MPIDR_EL1
+	and	x10, x10, _core_id_mask
+	add	x10, x10, #1
+	ldr	x11, =__exception_stack_size
+	ADR_REL	\register, __exception_stacks_start
+	madd	\register, x10, x11, \register
+.endm
+
+// Drop to EL1 from whatever level the firmware handed over, then continue at `entry` in EL1t with
+// `stack` as SP_EL0 and `exception_stack` as SP_EL1. x0-x7 are passed through untouched, x9 is
+// clobbered.
+.macro DROP_TO_EL1 entry, stack, exception_stack
+	// Kernels built for the hard-float target use FP/SIMD registers anywhere. Enabling them is
+	// harmless for soft-float builds, which never touch them.
+	mov	x9, _cpacr_el1_fpen
+	msr	CPACR_EL1, x9
+
+	// SP_EL0 can only be written while it is not the selected stack pointer.
+	msr	SPSel, #1
+	msr	SP_EL0, \stack
+
+	mrs	x9, CurrentEL
+	cmp	x9, _el3
+	b.eq	1f
+	cmp	x9, _el2
+	b.eq	2f
+
+	// Already running in EL1.
+	mov	sp, \exception_stack
+	msr	SPSel, #0
+	b	\entry
+
+	// EL3 -> EL2
+1:
+	mov	x9, _scr_el3_value
+	msr	SCR_EL3, x9
+	msr	CPTR_EL3, xzr
+
+	mov	x9, _spsr_el2h_masked
+	msr	SPSR_EL3, x9
+	ADR_REL	x9, 2f
+	msr	ELR_EL3, x9
+
+	eret
+
+	// EL2 -> EL1
+2:
+	// Give EL1 access to the timer and counter, without a virtual offset.
+	mrs	x9, CNTHCTL_EL2
+	orr	x9, x9, _cnthctl_el2_el1_access
+	msr	CNTHCTL_EL2, x9
+	msr	CNTVOFF_EL2, xzr
+
+	mov	x9, _cptr_el2_value
+	msr	CPTR_EL2, x9
+
+	mov	x9, _hcr_el2_value
+	msr	HCR_EL2, x9
+
+	ldr	x9, =_sctlr_el1_value
+	msr	SCTLR_EL1, x9
+
+	// "Return" to EL1t with all interrupts masked, straight into `entry`.
+	mov	x9, _spsr_el1t_masked
+	msr	SPSR_EL2, x9
+	ADR_REL	x9, \entry
+	msr	ELR_EL2, x9
+
+	msr	SP_EL1, \exception_stack
+
+	eret
+.endm
+
 //--------------------------------------------------------------------------------------------------
 // Public Code
 //--------------------------------------------------------------------------------------------------
@@ -38,24 +141,21 @@ _start:
 
 	// If execution reaches here, it is the boot core.
 
-	// Initialize DRAM.
-	ADR_REL	x0, __bss_start
-	ADR_REL x1, __bss_end_exclusive
+	// Initialize DRAM. x0 holds the device tree address, keep it for Rust.
+	ADR_REL	x3, __bss_start
+	ADR_REL x4, __bss_end_exclusive
 
 .L_bss_init_loop:
-	cmp	x0, x1
+	cmp	x3, x4
 	b.eq	.L_prepare_rust
-	stp	xzr, xzr, [x0], #16
+	stp	xzr, xzr, [x3], #16
 	b	.L_bss_init_loop
 
 	// Prepare the jump to Rust code.
 .L_prepare_rust:
-	// Set the stack pointer.
-	ADR_REL	x0, __boot_core_stack_end_exclusive
-	mov	sp, x0
-
-	// Jump to Rust code.
-	b	_start_rust
+	ADR_REL	x1, __boot_core_stack_end_exclusive
+	EXCEPTION_STACK_END x2
+	DROP_TO_EL1 _start_rust, x1, x2
 
 	// Infinitely wait for events (aka "park the core").
 .L_parking_loop:
@@ -65,3 +165,28 @@ _start:
 .size	_start, . - _start
 .type	_start, function
 .global	_start
+
+//------------------------------------------------------------------------------
+// fn _start_secondary()
+//
+// Entry point for cores 1-3 once the boot core releases them from the firmware's spin table. The
+// MMU and caches are off.
+//------------------------------------------------------------------------------
+_start_secondary:
+	mrs	x0, MPIDR_EL1
+	and	x0, x0, _core_id_mask
+
+	// Core n uses the n-th stack slot. Slots are in ascending order, so the end of slot n is
+	// __secondary_core_stacks_start + n * __secondary_core_stack_slot_size.
+	ADR_REL	x1, __secondary_core_stacks_start
+	ldr	x2, =__secondary_core_stack_slot_size
+	madd	x1, x0, x2, x1
+
+	EXCEPTION_STACK_END x2
+
+	// x0 carries the core ID into Rust.
+	DROP_TO_EL1 _start_rust_secondary, x1, x2
+
+.size	_start_secondary, . - _start_secondary
+.type	_start_secondary, function
+.global	_start_secondary
@@ -0,0 +1,421 @@
+//! Architectural synchronous and asynchronous exception handling.
+//!
+//! # Orientation
+//!
+//! Since arch modules are imported into generic modules using the path
+//! attribute, the path of this file is:
+//!
+//! crate::exception::arch_exception
+
+use core::cell::UnsafeCell;
+use core::fmt;
+
+use cortex_a::asm::barrier;
+use cortex_a::registers::*;
+use tock_registers::interfaces::{Readable, Writeable};
+use tock_registers::registers::InMemoryRegister;
+
+use crate::bsp::memory::mmu::virt_mem_layout;
+use crate::exception::asynchronous::interface::IRQManager;
+use crate::exception::asynchronous::irq_manager;
+use crate::exception::PrivilegeLevel;
+use crate::panic_println;
+
+// Kernels built for the hard-float target save the FP/SIMD registers on
+// exception entry.
+#[cfg(target_feature = "fp")]
+core::arch::global_asm!(".equ _save_fp_context, 1", include_str!("exception.s"));
+#[cfg(not(target_feature = "fp"))]
+core::arch::global_asm!(".equ _save_fp_context, 0", include_str!("exception.s"));
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Wrapper struct for memory copies of registers.
+#[repr(transparent)]
+struct SpsrEL1(InMemoryRegister<u64, SPSR_EL1::Register>);
+
+#[repr(transparent)]
+struct EsrEL1(InMemoryRegister<u64, ESR_EL1::Register>);
+
+/// The exception context as it is stored on the stack on exception entry.
+///
+/// Layout must match `CALL_WITH_CONTEXT` in exception.s.
+#[repr(C)]
+struct ExceptionContext {
+  /// General Purpose Registers x0-x29.
+  gpr: [u64; 30],
+
+  /// The link register, aka x30.
+  lr: u64,
+
+  /// Exception link register. The program counter at the time the exception
+  /// happened.
+  elr_el1: u64,
+
+  /// Saved program status.
+  spsr_el1: SpsrEL1,
+
+  /// Exception syndrome register.
+  esr_el1: EsrEL1,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// Dump the exception context and panic.
+fn default_exception_handler(origin: &str, e: &ExceptionContext) -> ! {
+  panic_println!("\nCPU Exception ({})\n{}", origin, e);
+
+  if let Some(guard) = e.stack_overflow() {
+    panic!("Stack overflow into {}, SP: {:#018x}", guard, e.interrupted_sp());
+  }
+
+  panic!("Unhandled CPU exception: {}", origin)
+}
+
+//------------------------------------------------------------------------------
+// Current, EL0
+//------------------------------------------------------------------------------
+
+#[no_mangle]
+unsafe extern "C" fn current_el0_synchronous(e: &mut ExceptionContext) {
+  default_exception_handler("current EL, SP_EL0, synchronous", e);
+}
+
+/// The kernel runs on SP_EL0, so this is where its IRQs arrive.
+#[no_mangle]
+unsafe extern "C" fn current_el0_irq(_e: &mut ExceptionContext) {
+  irq_manager().handle_pending_irqs();
+}
+
+#[no_mangle]
+unsafe extern "C" fn current_el0_fiq(e: &mut ExceptionContext) {
+  default_exception_handler("current EL, SP_EL0, FIQ", e);
+}
+
+#[no_mangle]
+unsafe extern "C" fn current_el0_serror(e: &mut ExceptionContext) {
+  default_exception_handler("current EL, SP_EL0, SError", e);
+}
+
+//------------------------------------------------------------------------------
+// Current, ELx
+//------------------------------------------------------------------------------
+
+#[no_mangle]
+unsafe extern "C" fn current_elx_synchronous(e: &mut ExceptionContext) {
+  default_exception_handler("current EL, SP_ELx, synchronous", e);
+}
+
+#[no_mangle]
+unsafe extern "C" fn current_elx_irq(e: &mut ExceptionContext) {
+  default_exception_handler("current EL, SP_ELx, IRQ", e);
+}
+
+#[no_mangle]
+unsafe extern "C" fn current_elx_fiq(e: &mut ExceptionContext) {
+  default_exception_handler("current EL, SP_ELx, FIQ", e);
+}
+
+#[no_mangle]
+unsafe extern "C" fn current_elx_serror(e: &mut ExceptionContext) {
+  default_exception_handler("current EL, SP_ELx, SError", e);
+}
+
+//------------------------------------------------------------------------------
+// Lower, AArch64
+//------------------------------------------------------------------------------
+
+#[no_mangle]
+unsafe extern "C" fn lower_aarch64_synchronous(e: &mut ExceptionContext) {
+  default_exception_handler("lower EL, AArch64, synchronous", e);
+}
+
+#[no_mangle]
+unsafe extern "C" fn lower_aarch64_irq(e: &mut ExceptionContext) {
+  default_exception_handler("lower EL, AArch64, IRQ", e);
+}
+
+#[no_mangle]
+unsafe extern "C" fn lower_aarch64_fiq(e: &mut ExceptionContext) {
+  default_exception_handler("lower EL, AArch64, FIQ", e);
+}
+
+#[no_mangle]
+unsafe extern "C" fn lower_aarch64_serror(e: &mut ExceptionContext) {
+  default_exception_handler("lower EL, AArch64, SError", e);
+}
+
+//------------------------------------------------------------------------------
+// Lower, AArch32
+//------------------------------------------------------------------------------
+
+#[no_mangle]
+unsafe extern "C" fn lower_aarch32_synchronous(e: &mut ExceptionContext) {
+  default_exception_handler("lower EL, AArch32, synchronous", e);
+}
+
+#[no_mangle]
+unsafe extern "C" fn lower_aarch32_irq(e: &mut ExceptionContext) {
+  default_exception_handler("lower EL, AArch32, IRQ", e);
+}
+
+#[no_mangle]
+unsafe extern "C" fn lower_aarch32_fiq(e: &mut ExceptionContext) {
+  default_exception_handler("lower EL, AArch32, FIQ", e);
+}
+
+#[no_mangle]
+unsafe extern "C" fn lower_aarch32_serror(e: &mut ExceptionContext) {
+  default_exception_handler("lower EL, AArch32, SError", e);
+}
+
+//------------------------------------------------------------------------------
+// Misc
+//------------------------------------------------------------------------------
+
+/// Human readable SPSR_EL1.
+impl fmt::Display for SpsrEL1 {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(f, "SPSR_EL1: {:#010x}", self.0.get())?;
+
+    let to_flag_str = |x| -> _ {
+      if x {
+        "Set"
+      } else {
+        "Not set"
+      }
+    };
+
+    writeln!(f, "      Flags:")?;
+    writeln!(
+      f,
+      "            Negative (N): {}",
+      to_flag_str(self.0.is_set(SPSR_EL1::N))
+    )?;
+    writeln!(
+      f,
+      "            Zero     (Z): {}",
+      to_flag_str(self.0.is_set(SPSR_EL1::Z))
+    )?;
+    writeln!(
+      f,
+      "            Carry    (C): {}",
+      to_flag_str(self.0.is_set(SPSR_EL1::C))
+    )?;
+    writeln!(
+      f,
+      "            Overflow (V): {}",
+      to_flag_str(self.0.is_set(SPSR_EL1::V))
+    )?;
+
+    let to_mask_str = |x| -> _ {
+      if x {
+        "Masked"
+      } else {
+        "Unmasked"
+      }
+    };
+
+    writeln!(f, "      Exception handling state:")?;
+    writeln!(f, "            Debug  (D): {}", to_mask_str(self.0.is_set(SPSR_EL1::D)))?;
+    writeln!(f, "            SError (A): {}", to_mask_str(self.0.is_set(SPSR_EL1::A)))?;
+    writeln!(f, "            IRQ    (I): {}", to_mask_str(self.0.is_set(SPSR_EL1::I)))?;
+    writeln!(f, "            FIQ    (F): {}", to_mask_str(self.0.is_set(SPSR_EL1::F)))?;
+
+    write!(
+      f,
+      "      Illegal Execution State (IL): {}",
+      to_flag_str(self.0.is_set(SPSR_EL1::IL))
+    )
+  }
+}
+
+impl EsrEL1 {
+  #[inline(always)]
+  fn exception_class(&self) -> Option<ESR_EL1::EC::Value> {
+    self.0.read_as_enum(ESR_EL1::EC)
+  }
+
+  /// Data and instruction aborts are the only classes for which FAR_EL1 holds a
+  /// valid address.
+  fn is_abort(&self) -> bool {
+    matches!(
+      self.exception_class(),
+      Some(ESR_EL1::EC::Value::DataAbortCurrentEL)
+        | Some(ESR_EL1::EC::Value::DataAbortLowerEL)
+        | Some(ESR_EL1::EC::Value::InstrAbortCurrentEL)
+        | Some(ESR_EL1::EC::Value::InstrAbortLowerEL)
+    )
+  }
+
+  /// Human readable fault status code of an abort, taken from ISS[5:0].
+  fn fault_status(&self) -> &'static str {
+    match self.0.read(ESR_EL1::ISS) & 0b11_1111 {
+      0b00_0000..=0b00_0011 => "Address size fault",
+      0b00_0100..=0b00_0111 => "Translation fault",
+      0b00_1001..=0b00_1011 => "Access flag fault",
+      0b00_1101..=0b00_1111 => "Permission fault",
+      0b01_0000 => "Synchronous external abort",
+      0b10_0001 => "Alignment fault",
+      0b11_0000 => "TLB conflict abort",
+      _ => "N/A",
+    }
+  }
+}
+
+/// Human readable ESR_EL1.
+impl fmt::Display for EsrEL1 {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(f, "ESR_EL1: {:#010x}", self.0.get())?;
+
+    write!(
+      f,
+      "      Exception Class         (EC) : {:#x}",
+      self.0.read(ESR_EL1::EC)
+    )?;
+
+    let ec_translation = match self.exception_class() {
+      Some(ESR_EL1::EC::Value::Unknown) => "Unknown reason",
+      Some(ESR_EL1::EC::Value::TrappedWFIorWFE) => "Trapped WFI or WFE",
+      Some(ESR_EL1::EC::Value::TrappedFP) => "Trapped FP/SIMD access",
+      Some(ESR_EL1::EC::Value::IllegalExecutionState) => "Illegal execution state",
+      Some(ESR_EL1::EC::Value::SVC64) => "SVC64",
+      Some(ESR_EL1::EC::Value::HVC64) => "HVC64",
+      Some(ESR_EL1::EC::Value::SMC64) => "SMC64",
+      Some(ESR_EL1::EC::Value::TrappedMsrMrs) => "Trapped MSR, MRS or system instruction",
+      Some(ESR_EL1::EC::Value::InstrAbortLowerEL) => "Instruction Abort, lower EL",
+      Some(ESR_EL1::EC::Value::InstrAbortCurrentEL) => "Instruction Abort, current EL",
+      Some(ESR_EL1::EC::Value::PCAlignmentFault) => "PC alignment fault",
+      Some(ESR_EL1::EC::Value::DataAbortLowerEL) => "Data Abort, lower EL",
+      Some(ESR_EL1::EC::Value::DataAbortCurrentEL) => "Data Abort, current EL",
+      Some(ESR_EL1::EC::Value::SPAlignmentFault) => "SP alignment fault",
+      Some(ESR_EL1::EC::Value::SError) => "SError interrupt",
+      Some(ESR_EL1::EC::Value::Brk64) => "BRK instruction",
+      _ => "N/A",
+    };
+    writeln!(f, " - {}", ec_translation)?;
+
+    writeln!(
+      f,
+      "      Instr Specific Syndrome (ISS): {:#x}",
+      self.0.read(ESR_EL1::ISS)
+    )?;
+
+    if self.is_abort() {
+      // ISS[6] is WnR, only meaningful for data aborts.
+      let access = if self.0.read(ESR_EL1::ISS) & (1 << 6) != 0 {
+        "write"
+      } else {
+        "read"
+      };
+      write!(f, "      Fault Status: {} ({})", self.fault_status(), access)
+    } else {
+      write!(
+        f,
+        "      Instruction Length      (IL) : {}",
+        if self.0.is_set(ESR_EL1::IL) { "32 bit" } else { "16 bit" }
+      )
+    }
+  }
+}
+
+impl ExceptionContext {
+  /// The stack pointer of the interrupted code. The kernel runs on SP_EL0. Only
+  /// exceptions taken inside a handler interrupt SP_EL1, right above the
+  /// context pushed for them.
+  fn interrupted_sp(&self) -> u64 {
+    match self.spsr_el1.0.read_as_enum(SPSR_EL1::M) {
+      Some(SPSR_EL1::M::Value::EL1h) => self as *const Self as u64 + core::mem::size_of::<Self>() as u64,
+      _ => SP_EL0.get(),
+    }
+  }
+
+  /// A kernel data abort inside a stack guard is a stack overflow. Returns the
+  /// name of the guard.
+  fn stack_overflow(&self) -> Option<&'static str> {
+    if !matches!(
+      self.esr_el1.exception_class(),
+      Some(ESR_EL1::EC::Value::DataAbortCurrentEL)
+    ) {
+      return None;
+    }
+
+    virt_mem_layout()
+      .stack_guard_for(FAR_EL1.get() as usize)
+      .map(|guard| guard.name)
+  }
+}
+
+/// Human readable print of the exception context.
+impl fmt::Display for ExceptionContext {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(f, "{}", self.esr_el1)?;
+
+    if self.esr_el1.is_abort() {
+      writeln!(f, "FAR_EL1: {:#018x}", FAR_EL1.get() as usize)?;
+    }
+
+    if let Some(guard) = self.stack_overflow() {
+      writeln!(f, "Stack overflow into {}", guard)?;
+      writeln!(f, "SP: {:#018x}", self.interrupted_sp())?;
+    }
+
+    writeln!(f, "{}", self.spsr_el1)?;
+    writeln!(f, "ELR_EL1: {:#018x}", self.elr_el1)?;
+    writeln!(f)?;
+    writeln!(f, "General purpose register:")?;
+
+    let alternating = |x| -> _ {
+      if x % 2 == 0 {
+        "   "
+      } else {
+        "\n"
+      }
+    };
+
+    // Print two registers per line.
+    for (i, reg) in self.gpr.iter().enumerate() {
+      write!(f, "      x{: <2}: {: >#018x}{}", i, reg, alternating(i))?;
+    }
+    write!(f, "      lr : {:#018x}", self.lr)
+  }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// The processing element's current privilege level.
+pub fn current_privilege_level() -> (PrivilegeLevel, &'static str) {
+  let el = CurrentEL.read_as_enum(CurrentEL::EL);
+  match el {
+    Some(CurrentEL::EL::Value::EL2) => (PrivilegeLevel::Hypervisor, "EL2"),
+    Some(CurrentEL::EL::Value::EL1) => (PrivilegeLevel::Kernel, "EL1"),
+    Some(CurrentEL::EL::Value::EL0) => (PrivilegeLevel::User, "EL0"),
+    _ => (PrivilegeLevel::Unknown, "Unknown"),
+  }
+}
+
+/// Install the exception vector table.
+///
+/// # Safety
+///
+/// - Changes the HW state of the executing core.
+/// - The vector table and the symbol `__exception_vector_start` from
+///   exception.s must adhere to the alignment and size constraints demanded by
+///   the ARMv8-A Architecture Reference Manual.
+pub unsafe fn handling_init() {
+  // Provided by exception.s.
+  extern "Rust" {
+    static __exception_vector_start: UnsafeCell<()>;
+  }
+
+  VBAR_EL1.set(__exception_vector_start.get() as u64);
+
+  // Force VBAR update to complete before next instruction.
+  barrier::isb(barrier::SY);
+}
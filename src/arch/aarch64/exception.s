@@ -0,0 +1,201 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2018-2022 Andre Richter <andre.o.richter@gmail.com>
+
+//--------------------------------------------------------------------------------------------------
+// Definitions
+//--------------------------------------------------------------------------------------------------
+
+// Size of the saved context on the stack. Must match `ExceptionContext` in exception.rs.
+.equ _context_size, 16 * 17
+
+// Size of the FP/SIMD save area: FPCR and FPSR, then q0-q31.
+.equ _fp_context_size, 16 * 33
+
+// Save the GPRs and exception state on the stack, then continue in the shared
+// `__exception_call_handler` with a pointer to the saved context in x0 and the Rust handler in x1.
+//
+// Every vector slot is 0x80 bytes (32 instructions) long, so this must fit into one slot.
+.macro CALL_WITH_CONTEXT handler
+__vector_\handler:
+	sub	sp,  sp,  #_context_size
+
+	stp	x0,  x1,  [sp, #16 * 0]
+	stp	x2,  x3,  [sp, #16 * 1]
+	stp	x4,  x5,  [sp, #16 * 2]
+	stp	x6,  x7,  [sp, #16 * 3]
+	stp	x8,  x9,  [sp, #16 * 4]
+	stp	x10, x11, [sp, #16 * 5]
+	stp	x12, x13, [sp, #16 * 6]
+	stp	x14, x15, [sp, #16 * 7]
+	stp	x16, x17, [sp, #16 * 8]
+	stp	x18, x19, [sp, #16 * 9]
+	stp	x20, x21, [sp, #16 * 10]
+	stp	x22, x23, [sp, #16 * 11]
+	stp	x24, x25, [sp, #16 * 12]
+	stp	x26, x27, [sp, #16 * 13]
+	stp	x28, x29, [sp, #16 * 14]
+
+	mrs	x1,  ELR_EL1
+	mrs	x2,  SPSR_EL1
+	mrs	x3,  ESR_EL1
+
+	stp	lr,  x1,  [sp, #16 * 15]
+	stp	x2,  x3,  [sp, #16 * 16]
+
+	// x0 is the first argument for the function called through `\handler`.
+	mov	x0,  sp
+	adr	x1,  \handler
+
+	b	__exception_call_handler
+
+.size	__vector_\handler, . - __vector_\handler
+.type	__vector_\handler, function
+.endm
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+.section .text
+
+// The vector table must be 2 KiB aligned (VBAR_EL1 bits [10:0] are RES0).
+.align 11
+
+__exception_vector_start:
+
+// Current exception level with SP_EL0.
+.org 0x000
+	CALL_WITH_CONTEXT current_el0_synchronous
+.org 0x080
+	CALL_WITH_CONTEXT current_el0_irq
+.org 0x100
+	CALL_WITH_CONTEXT current_el0_fiq
+.org 0x180
+	CALL_WITH_CONTEXT current_el0_serror
+
+// Current exception level with SP_ELx, x > 0.
+.org 0x200
+	CALL_WITH_CONTEXT current_elx_synchronous
+.org 0x280
+	CALL_WITH_CONTEXT current_elx_irq
+.org 0x300
+	CALL_WITH_CONTEXT current_elx_fiq
+.org 0x380
+	CALL_WITH_CONTEXT current_elx_serror
+
+// Lower exception level, AArch64.
+.org 0x400
+	CALL_WITH_CONTEXT lower_aarch64_synchronous
+.org 0x480
+	CALL_WITH_CONTEXT lower_aarch64_irq
+.org 0x500
+	CALL_WITH_CONTEXT lower_aarch64_fiq
+.org 0x580
+	CALL_WITH_CONTEXT lower_aarch64_serror
+
+// Lower exception level, AArch32.
+.org 0x600
+	CALL_WITH_CONTEXT lower_aarch32_synchronous
+.org 0x680
+	CALL_WITH_CONTEXT lower_aarch32_irq
+.org 0x700
+	CALL_WITH_CONTEXT lower_aarch32_fiq
+.org 0x780
+	CALL_WITH_CONTEXT lower_aarch32_serror
+.org 0x800
+
+//------------------------------------------------------------------------------
+// fn __exception_call_handler(context: &mut ExceptionContext, handler: fn(&mut ExceptionContext))
+//------------------------------------------------------------------------------
+__exception_call_handler:
+// Kernels built for the hard-float target use the FP/SIMD registers anywhere, including in the
+// handlers. Preserve them for the interrupted code. `_save_fp_context` is defined by exception.rs.
+.if _save_fp_context
+	sub	sp,  sp,  #_fp_context_size
+
+	stp	q0,  q1,  [sp, #16 + 32 * 0]
+	stp	q2,  q3,  [sp, #16 + 32 * 1]
+	stp	q4,  q5,  [sp, #16 + 32 * 2]
+	stp	q6,  q7,  [sp, #16 + 32 * 3]
+	stp	q8,  q9,  [sp, #16 + 32 * 4]
+	stp	q10, q11, [sp, #16 + 32 * 5]
+	stp	q12, q13, [sp, #16 + 32 * 6]
+	stp	q14, q15, [sp, #16 + 32 * 7]
+	stp	q16, q17, [sp, #16 + 32 * 8]
+	stp	q18, q19, [sp, #16 + 32 * 9]
+	stp	q20, q21, [sp, #16 + 32 * 10]
+	stp	q22, q23, [sp, #16 + 32 * 11]
+	stp	q24, q25, [sp, #16 + 32 * 12]
+	stp	q26, q27, [sp, #16 + 32 * 13]
+	stp	q28, q29, [sp, #16 + 32 * 14]
+	stp	q30, q31, [sp, #16 + 32 * 15]
+
+	mrs	x2,  FPCR
+	mrs	x3,  FPSR
+	stp	x2,  x3,  [sp, #16 * 0]
+.endif
+
+	blr	x1
+
+.if _save_fp_context
+	ldp	x2,  x3,  [sp, #16 * 0]
+	msr	FPCR, x2
+	msr	FPSR, x3
+
+	ldp	q0,  q1,  [sp, #16 + 32 * 0]
+	ldp	q2,  q3,  [sp, #16 + 32 * 1]
+	ldp	q4,  q5,  [sp, #16 + 32 * 2]
+	ldp	q6,  q7,  [sp, #16 + 32 * 3]
+	ldp	q8,  q9,  [sp, #16 + 32 * 4]
+	ldp	q10, q11, [sp, #16 + 32 * 5]
+	ldp	q12, q13, [sp, #16 + 32 * 6]
+	ldp	q14, q15, [sp, #16 + 32 * 7]
+	ldp	q16, q17, [sp, #16 + 32 * 8]
+	ldp	q18, q19, [sp, #16 + 32 * 9]
+	ldp	q20, q21, [sp, #16 + 32 * 10]
+	ldp	q22, q23, [sp, #16 + 32 * 11]
+	ldp	q24, q25, [sp, #16 + 32 * 12]
+	ldp	q26, q27, [sp, #16 + 32 * 13]
+	ldp	q28, q29, [sp, #16 + 32 * 14]
+	ldp	q30, q31, [sp, #16 + 32 * 15]
+
+	add	sp,  sp,  #_fp_context_size
+.endif
+
+	b	__exception_restore_context
+
+.size	__exception_call_handler, . - __exception_call_handler
+.type	__exception_call_handler, function
+
+//------------------------------------------------------------------------------
+// fn __exception_restore_context()
+//------------------------------------------------------------------------------
+__exception_restore_context:
+	ldp	x19, x20, [sp, #16 * 16]
+	ldp	lr,  x21, [sp, #16 * 15]
+
+	msr	SPSR_EL1, x19
+	msr	ELR_EL1,  x21
+
+	ldp	x0,  x1,  [sp, #16 * 0]
+	ldp	x2,  x3,  [sp, #16 * 1]
+	ldp	x4,  x5,  [sp, #16 * 2]
+	ldp	x6,  x7,  [sp, #16 * 3]
+	ldp	x8,  x9,  [sp, #16 * 4]
+	ldp	x10, x11, [sp, #16 * 5]
+	ldp	x12, x13, [sp, #16 * 6]
+	ldp	x14, x15, [sp, #16 * 7]
+	ldp	x16, x17, [sp, #16 * 8]
+	ldp	x18, x19, [sp, #16 * 9]
+	ldp	x20, x21, [sp, #16 * 10]
+	ldp	x22, x23, [sp, #16 * 11]
+	ldp	x24, x25, [sp, #16 * 12]
+	ldp	x26, x27, [sp, #16 * 13]
+	ldp	x28, x29, [sp, #16 * 14]
+
+	add	sp,  sp,  #_context_size
+
+	eret
+
+.size	__exception_restore_context, . - __exception_restore_context
+.type	__exception_restore_context, function
@@ -0,0 +1,457 @@
+//! Memory Management Unit Driver.
+//!
+//! Only 64 KiB granule is supported. The kernel is identity mapped through a
+//! single level 2 table, which points to one level 3 table per 512 MiB of
+//! address space.
+//!
+//! # Orientation
+//!
+//! Since arch modules are imported into generic modules using the path
+//! attribute, the path of this file is:
+//!
+//! crate::mem::mmu::arch_mmu
+
+use core::arch::asm;
+use core::ops::RangeInclusive;
+
+use cortex_a::asm::barrier;
+use cortex_a::registers::*;
+use tock_registers::interfaces::{ReadWriteable, Readable, Writeable};
+use tock_registers::register_bitfields;
+use tock_registers::registers::InMemoryRegister;
+
+use crate::bsp;
+use crate::mem::mmu::{AccessPermissions, AttributeFields, MMUEnableError, MemAttributes};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+// A table descriptor, as per ARMv8-A Architecture Reference Manual Figure
+// D5-15.
+register_bitfields! {u64,
+  STAGE1_TABLE_DESCRIPTOR [
+    /// Physical address of the next descriptor.
+    NEXT_LEVEL_TABLE_ADDR_64KiB OFFSET(16) NUMBITS(32) [], // [47:16]
+
+    TYPE  OFFSET(1) NUMBITS(1) [
+      Block = 0,
+      Table = 1
+    ],
+
+    VALID OFFSET(0) NUMBITS(1) [
+      False = 0,
+      True = 1
+    ]
+  ]
+}
+
+// A level 3 page descriptor, as per ARMv8-A Architecture Reference Manual
+// Figure D5-17.
+register_bitfields! {u64,
+  STAGE1_PAGE_DESCRIPTOR [
+    /// Unprivileged execute-never.
+    UXN      OFFSET(54) NUMBITS(1) [
+      False = 0,
+      True = 1
+    ],
+
+    /// Privileged execute-never.
+    PXN      OFFSET(53) NUMBITS(1) [
+      False = 0,
+      True = 1
+    ],
+
+    /// Physical address of the next table descriptor (lvl2) or the page descriptor (lvl3).
+    OUTPUT_ADDR_64KiB OFFSET(16) NUMBITS(32) [], // [47:16]
+
+    /// Access flag.
+    AF       OFFSET(10) NUMBITS(1) [
+      False = 0,
+      True = 1
+    ],
+
+    /// Shareability field.
+    SH       OFFSET(8) NUMBITS(2) [
+      OuterShareable = 0b10,
+      InnerShareable = 0b11
+    ],
+
+    /// Access Permissions.
+    AP       OFFSET(6) NUMBITS(2) [
+      RW_EL1 = 0b00,
+      RW_EL1_EL0 = 0b01,
+      RO_EL1 = 0b10,
+      RO_EL1_EL0 = 0b11
+    ],
+
+    /// Memory attributes index into the MAIR_EL1 register.
+    AttrIndx OFFSET(2) NUMBITS(3) [],
+
+    TYPE     OFFSET(1) NUMBITS(1) [
+      Reserved_Invalid = 0,
+      Page = 1
+    ],
+
+    VALID    OFFSET(0) NUMBITS(1) [
+      False = 0,
+      True = 1
+    ]
+  ]
+}
+
+/// Constants for indexing the MAIR_EL1.
+mod mair {
+  pub const DEVICE: u64 = 0;
+  pub const NORMAL: u64 = 1;
+  pub const NORMAL_NON_CACHEABLE: u64 = 2;
+}
+
+const GRANULE_SHIFT: usize = 16;
+const GRANULE_SIZE: usize = 1 << GRANULE_SHIFT;
+
+/// Each level 2 entry covers 512 MiB.
+const LVL2_SHIFT: usize = 29;
+
+/// Entries in a level 3 table of 64 KiB granule.
+const LVL3_ENTRIES: usize = 1 << (LVL2_SHIFT - GRANULE_SHIFT);
+
+/// Number of address bits the BSP layout needs.
+const ADDR_SPACE_BITS: usize = 64 - bsp::memory::map::END_INCLUSIVE.leading_zeros() as usize;
+
+/// The level 2 table must cover the whole address space the hardware walks.
+const LVL2_ENTRIES: usize = 1 << (ADDR_SPACE_BITS - LVL2_SHIFT);
+
+/// Level 3 tables are only needed up to the end of the BSP layout.
+const NUM_LVL3_TABLES: usize = (bsp::memory::map::END_INCLUSIVE >> LVL2_SHIFT) + 1;
+
+/// A table descriptor for 64 KiB aperture.
+///
+/// The output points to the next table.
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct TableDescriptor {
+  value: u64,
+}
+
+/// A page descriptor with 64 KiB aperture.
+///
+/// The output points to physical memory.
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct PageDescriptor {
+  value: u64,
+}
+
+/// Big monolithic struct for storing the translation tables. Individual levels
+/// must be 64 KiB aligned, so the lvl3 is put first.
+#[repr(C)]
+#[repr(align(65536))]
+struct FixedSizeTranslationTable {
+  /// Page descriptors, covering 64 KiB windows per entry.
+  lvl3: [[PageDescriptor; LVL3_ENTRIES]; NUM_LVL3_TABLES],
+
+  /// Table descriptors, covering 512 MiB windows.
+  lvl2: [TableDescriptor; LVL2_ENTRIES],
+}
+
+/// Memory Management Unit type.
+struct MemoryManagementUnit;
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+/// The kernel translation tables.
+///
+/// # Safety
+///
+/// - Supposed to land in `.bss`. Therefore, ensure that all initial member
+///   values boil down to "0".
+static mut KERNEL_TABLES: FixedSizeTranslationTable = FixedSizeTranslationTable::new();
+
+static MMU: MemoryManagementUnit = MemoryManagementUnit;
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl TableDescriptor {
+  /// Create an instance pointing to the supplied address.
+  fn from_next_lvl_table_addr(phys_next_lvl_table_addr: usize) -> Self {
+    let val = InMemoryRegister::<u64, STAGE1_TABLE_DESCRIPTOR::Register>::new(0);
+
+    let shifted = phys_next_lvl_table_addr >> GRANULE_SHIFT;
+    val.write(
+      STAGE1_TABLE_DESCRIPTOR::NEXT_LEVEL_TABLE_ADDR_64KiB.val(shifted as u64)
+        + STAGE1_TABLE_DESCRIPTOR::TYPE::Table
+        + STAGE1_TABLE_DESCRIPTOR::VALID::True,
+    );
+
+    TableDescriptor { value: val.get() }
+  }
+}
+
+/// Convert the kernel's generic memory attributes to HW-specific attributes of
+/// the MMU.
+impl From<AttributeFields> for tock_registers::fields::FieldValue<u64, STAGE1_PAGE_DESCRIPTOR::Register> {
+  fn from(attribute_fields: AttributeFields) -> Self {
+    // Memory attributes.
+    let mut desc = match attribute_fields.mem_attributes {
+      MemAttributes::CacheableDRAM => {
+        STAGE1_PAGE_DESCRIPTOR::SH::InnerShareable + STAGE1_PAGE_DESCRIPTOR::AttrIndx.val(mair::NORMAL)
+      },
+      MemAttributes::WriteCombining => {
+        STAGE1_PAGE_DESCRIPTOR::SH::OuterShareable + STAGE1_PAGE_DESCRIPTOR::AttrIndx.val(mair::NORMAL_NON_CACHEABLE)
+      },
+      MemAttributes::Device => {
+        STAGE1_PAGE_DESCRIPTOR::SH::OuterShareable + STAGE1_PAGE_DESCRIPTOR::AttrIndx.val(mair::DEVICE)
+      },
+    };
+
+    // Access Permissions.
+    desc += match attribute_fields.acc_perms {
+      AccessPermissions::ReadOnly => STAGE1_PAGE_DESCRIPTOR::AP::RO_EL1,
+      AccessPermissions::ReadWrite => STAGE1_PAGE_DESCRIPTOR::AP::RW_EL1,
+    };
+
+    // The execute-never attribute is mapped to PXN in AArch64.
+    desc += if attribute_fields.execute_never {
+      STAGE1_PAGE_DESCRIPTOR::PXN::True
+    } else {
+      STAGE1_PAGE_DESCRIPTOR::PXN::False
+    };
+
+    // Always set unprivileged exectue-never as long as userspace is not
+    // implemented yet.
+    desc += STAGE1_PAGE_DESCRIPTOR::UXN::True;
+
+    desc
+  }
+}
+
+impl PageDescriptor {
+  /// Create an instance pointing to the supplied address.
+  fn from_output_addr(phys_output_addr: usize, attribute_fields: AttributeFields) -> Self {
+    let val = InMemoryRegister::<u64, STAGE1_PAGE_DESCRIPTOR::Register>::new(0);
+
+    let shifted = phys_output_addr as u64 >> GRANULE_SHIFT;
+    val.write(
+      STAGE1_PAGE_DESCRIPTOR::OUTPUT_ADDR_64KiB.val(shifted)
+        + STAGE1_PAGE_DESCRIPTOR::AF::True
+        + STAGE1_PAGE_DESCRIPTOR::TYPE::Page
+        + STAGE1_PAGE_DESCRIPTOR::VALID::True
+        + attribute_fields.into(),
+    );
+
+    Self { value: val.get() }
+  }
+
+  /// An invalid descriptor. Accesses fault.
+  const fn invalid() -> Self {
+    Self { value: 0 }
+  }
+}
+
+impl FixedSizeTranslationTable {
+  /// Create an instance.
+  const fn new() -> Self {
+    Self {
+      lvl3: [[PageDescriptor::invalid(); LVL3_ENTRIES]; NUM_LVL3_TABLES],
+      lvl2: [TableDescriptor { value: 0 }; LVL2_ENTRIES],
+    }
+  }
+
+  /// Iterates over all static translation table entries and fills them at
+  /// once.
+  fn populate_tt_entries(&mut self) -> Result<(), &'static str> {
+    let layout = bsp::memory::mmu::virt_mem_layout();
+
+    for (l2_nr, l2_entry) in self.lvl2.iter_mut().take(NUM_LVL3_TABLES).enumerate() {
+      *l2_entry = TableDescriptor::from_next_lvl_table_addr(self.lvl3[l2_nr].as_ptr() as usize);
+
+      for (l3_nr, l3_entry) in self.lvl3[l2_nr].iter_mut().enumerate() {
+        let addr = (l2_nr << LVL2_SHIFT) + (l3_nr << GRANULE_SHIFT);
+
+        // Everything past the layout stays invalid.
+        if addr > layout.max_virt_addr_inclusive() {
+          break;
+        }
+
+        // Stack guards stay invalid, too.
+        if layout.stack_guard_for(addr).is_some() {
+          continue;
+        }
+
+        *l3_entry = PageDescriptor::from_output_addr(addr, layout.attributes_for(addr)?);
+      }
+    }
+
+    Ok(())
+  }
+
+  /// The base address, to be stored in TTBR0_EL1.
+  fn phys_base_address(&self) -> u64 {
+    self.lvl2.as_ptr() as u64
+  }
+
+  /// The level 3 entry responsible for an address.
+  fn page_descriptor_mut(&mut self, addr: usize) -> Option<&mut PageDescriptor> {
+    self
+      .lvl3
+      .get_mut(addr >> LVL2_SHIFT)
+      .map(|table| &mut table[(addr >> GRANULE_SHIFT) & (LVL3_ENTRIES - 1)])
+  }
+}
+
+impl MemoryManagementUnit {
+  /// Setup function for the MAIR_EL1 register.
+  fn set_up_mair(&self) {
+    // Define the memory types being mapped.
+    MAIR_EL1.write(
+      // Attribute 2 - Normal, non-cacheable. Used for write-combining.
+      MAIR_EL1::Attr2_Normal_Outer::NonCacheable
+        + MAIR_EL1::Attr2_Normal_Inner::NonCacheable
+
+        // Attribute 1 - Cacheable normal DRAM.
+        + MAIR_EL1::Attr1_Normal_Outer::WriteBack_NonTransient_ReadWriteAlloc
+        + MAIR_EL1::Attr1_Normal_Inner::WriteBack_NonTransient_ReadWriteAlloc
+
+        // Attribute 0 - Device.
+        + MAIR_EL1::Attr0_Device::nonGathering_nonReordering_EarlyWriteAck,
+    );
+  }
+
+  /// Configure various settings of stage 1 of the EL1 translation regime.
+  fn configure_translation_control(&self) {
+    let t0sz = (64 - ADDR_SPACE_BITS) as u64;
+
+    TCR_EL1.write(
+      TCR_EL1::TBI0::Used
+        + TCR_EL1::IPS::Bits_40
+        + TCR_EL1::TG0::KiB_64
+        + TCR_EL1::SH0::Inner
+        + TCR_EL1::ORGN0::WriteBack_ReadAlloc_WriteAlloc_Cacheable
+        + TCR_EL1::IRGN0::WriteBack_ReadAlloc_WriteAlloc_Cacheable
+        + TCR_EL1::EPD0::EnableTTBR0Walks
+        + TCR_EL1::A1::TTBR0
+        + TCR_EL1::T0SZ.val(t0sz)
+        + TCR_EL1::EPD1::DisableTTBR1Walks,
+    );
+  }
+}
+
+/// Smallest data cache line size of all caches in the system.
+#[inline(always)]
+fn dcache_line_size() -> usize {
+  let ctr: u64;
+  unsafe { asm!("mrs {}, CTR_EL0", out(reg) ctr, options(nomem, nostack)) };
+  4 << ((ctr >> 16) & 0xF)
+}
+
+/// Clean and invalidate a range from the data caches, to the point of
+/// coherency.
+fn clean_invalidate_dcache_range(range: &RangeInclusive<usize>) {
+  let line = dcache_line_size();
+  let mut addr = range.start() & !(line - 1);
+  while addr <= *range.end() {
+    unsafe { asm!("dc civac, {}", in(reg) addr, options(nostack)) };
+    addr += line;
+  }
+  unsafe { barrier::dsb(barrier::SY) };
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Return a reference to the MMU instance.
+pub fn mmu() -> &'static impl crate::mem::mmu::interface::Mmu {
+  &MMU
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+
+impl crate::mem::mmu::interface::Mmu for MemoryManagementUnit {
+  unsafe fn populate_translation_tables(&self) -> Result<(), &'static str> {
+    KERNEL_TABLES.populate_tt_entries()
+  }
+
+  unsafe fn enable_mmu_and_caching(&self) -> Result<(), MMUEnableError> {
+    if self.is_enabled() {
+      return Err(MMUEnableError::AlreadyEnabled);
+    }
+
+    // Fail early if translation granule is not supported.
+    if !ID_AA64MMFR0_EL1.matches_all(ID_AA64MMFR0_EL1::TGran64::Supported) {
+      return Err(MMUEnableError::Other("Translation granule not supported in HW"));
+    }
+
+    // Prepare the memory attribute indirection register.
+    self.set_up_mair();
+
+    // Set the "Translation Table Base Register".
+    TTBR0_EL1.set_baddr(KERNEL_TABLES.phys_base_address());
+
+    self.configure_translation_control();
+
+    // Drop anything the firmware might have left in the TLB.
+    asm!("tlbi vmalle1", options(nostack));
+    barrier::dsb(barrier::ISH);
+
+    // Switch the MMU on.
+    //
+    // First, force all previous changes to be seen before the MMU is enabled.
+    barrier::isb(barrier::SY);
+
+    // Enable the MMU and turn on data and instruction caching.
+    SCTLR_EL1.modify(SCTLR_EL1::M::Enable + SCTLR_EL1::C::Cacheable + SCTLR_EL1::I::Cacheable);
+
+    // Force MMU init to complete before next instruction.
+    barrier::isb(barrier::SY);
+
+    Ok(())
+  }
+
+  #[inline(always)]
+  fn is_enabled(&self) -> bool {
+    SCTLR_EL1.matches_all(SCTLR_EL1::M::Enable)
+  }
+
+  unsafe fn remap(&self, range: RangeInclusive<usize>, attributes: AttributeFields) -> Result<(), &'static str> {
+    if !self.is_enabled() {
+      return Err("MMU not enabled");
+    }
+
+    if *range.end() > bsp::memory::map::END_INCLUSIVE {
+      return Err("Address out of range");
+    }
+
+    let start = range.start() & !(GRANULE_SIZE - 1);
+    let mut addr = start;
+    while addr <= *range.end() {
+      let entry = KERNEL_TABLES.page_descriptor_mut(addr).ok_or("Address out of range")?;
+
+      // Break-before-make: the old entry must be gone from all TLBs before the
+      // memory type changes.
+      *entry = PageDescriptor::invalid();
+      barrier::dsb(barrier::ISHST);
+      asm!("tlbi vaae1is, {}", in(reg) addr >> 12, options(nostack));
+      barrier::dsb(barrier::ISH);
+
+      *entry = PageDescriptor::from_output_addr(addr, attributes);
+      addr += GRANULE_SIZE;
+    }
+
+    barrier::dsb(barrier::ISHST);
+    barrier::isb(barrier::SY);
+
+    // Lines cached through the old mapping must not be written back over data
+    // written through the new one.
+    clean_invalidate_dcache_range(&(start..=addr - 1));
+
+    Ok(())
+  }
+}
@@ -0,0 +1,93 @@
+//! Architectural performance monitors.
+//!
+//! Uses the 64 bit cycle counter and the first three event counters. cortex-a
+//! has no accessors for the PMU registers.
+//!
+//! # Orientation
+//!
+//! Since arch modules are imported into generic modules using the path
+//! attribute, the path of this file is:
+//!
+//! crate::pmu::arch_pmu
+
+use core::arch::asm;
+
+use cortex_a::asm::barrier;
+
+use crate::pmu::Counters;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// PMCR_EL0: enable, reset the event counters and the cycle counter, and let
+/// the cycle counter overflow at 64 bits.
+const PMCR_E: u64 = 1 << 0;
+const PMCR_P: u64 = 1 << 1;
+const PMCR_C: u64 = 1 << 2;
+const PMCR_LC: u64 = 1 << 6;
+
+/// Enable bits in PMCNTENSET_EL0.
+const PMCNTEN_CYCLES: u64 = 1 << 31;
+const PMCNTEN_EVENTS: u64 = 0b111;
+
+// Common architectural event numbers.
+const EVENT_L1D_CACHE_REFILL: u64 = 0x03;
+const EVENT_INST_RETIRED: u64 = 0x08;
+const EVENT_L2D_CACHE_REFILL: u64 = 0x17;
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Program and start the counters of the executing core. Every core that
+/// samples them runs this once.
+pub fn init() {
+  unsafe {
+    asm!(
+      "msr pmevtyper0_el0, {inst}",
+      "msr pmevtyper1_el0, {l1d}",
+      "msr pmevtyper2_el0, {l2d}",
+      // Count in all exception levels.
+      "msr pmccfiltr_el0, xzr",
+      "msr pmcntenset_el0, {enable}",
+      "msr pmcr_el0, {pmcr}",
+      "isb",
+      inst = in(reg) EVENT_INST_RETIRED,
+      l1d = in(reg) EVENT_L1D_CACHE_REFILL,
+      l2d = in(reg) EVENT_L2D_CACHE_REFILL,
+      enable = in(reg) PMCNTEN_CYCLES | PMCNTEN_EVENTS,
+      pmcr = in(reg) PMCR_E | PMCR_P | PMCR_C | PMCR_LC,
+      options(nostack),
+    );
+  }
+}
+
+/// Sample the counters of the executing core.
+#[inline(always)]
+pub fn read() -> Counters {
+  let (cycles, instructions, l1d_refills, l2d_refills): (u64, u64, u64, u64);
+
+  unsafe {
+    // Don't let the sample move across the surrounding code.
+    barrier::isb(barrier::SY);
+    asm!(
+      "mrs {cycles}, pmccntr_el0",
+      "mrs {inst}, pmevcntr0_el0",
+      "mrs {l1d}, pmevcntr1_el0",
+      "mrs {l2d}, pmevcntr2_el0",
+      cycles = out(reg) cycles,
+      inst = out(reg) instructions,
+      l1d = out(reg) l1d_refills,
+      l2d = out(reg) l2d_refills,
+      options(nomem, nostack, preserves_flags),
+    );
+  }
+
+  Counters {
+    cycles,
+    instructions,
+    l1d_refills,
+    l2d_refills,
+  }
+}
@@ -11,13 +11,18 @@
 //!
 //! crate::time::arch_time
 
+use core::arch::asm;
 use core::time::Duration;
 
 use cortex_a::asm::barrier;
 use cortex_a::registers::*;
 use tock_registers::interfaces::{ReadWriteable, Readable, Writeable};
 
-use crate::{time, warn};
+use crate::bsp::exception::asynchronous::irq_map::ARM_NS_PHYSICAL_TIMER;
+use crate::exception::asynchronous::interface::IRQManager;
+use crate::exception::asynchronous::{irq_manager, IRQDescriptor};
+use crate::time::Instant;
+use crate::{cpu, time};
 
 //--------------------------------------------------------------------------------------------------
 // Private Definitions
@@ -45,14 +50,55 @@ impl GenericTimer {
     unsafe { barrier::isb(barrier::SY) };
     CNTPCT_EL0.get()
   }
+
+  /// The counter value at `uptime`, saturating.
+  fn uptime_to_count(&self, uptime: Duration) -> u64 {
+    let count = uptime.as_nanos() * CNTFRQ_EL0.get() as u128 / NS_PER_S as u128;
+    count.min(u64::MAX as u128) as u64
+  }
+}
+
+/// The timer fired. Mask it, so the level triggered IRQ goes away; the
+/// sleeping core checks its deadline once it is back.
+fn handle_timer_irq() {
+  CNTP_CTL_EL0.modify(CNTP_CTL_EL0::IMASK::SET);
 }
 
 //--------------------------------------------------------------------------------------------------
 // Public Code
 //--------------------------------------------------------------------------------------------------
 
-/// Return a reference to the time manager.
-pub fn time_manager() -> &'static impl time::interface::TimeManager {
+/// Register the timer IRQ handler and enable the IRQ on the boot core.
+///
+/// # Safety
+///
+/// - Must run once, on the boot core, after the interrupt controller is set up.
+pub unsafe fn init() {
+  let descriptor = IRQDescriptor {
+    name: "Generic timer",
+    handler: handle_timer_irq,
+  };
+
+  if let Err(err) = irq_manager().register_handler(ARM_NS_PHYSICAL_TIMER, descriptor) {
+    panic!("Timer IRQ: {}", err);
+  }
+
+  init_secondary_core();
+}
+
+/// Enable the timer IRQ on the executing core. Each core has its own timer.
+///
+/// # Safety
+///
+/// - Must run after `init`.
+pub unsafe fn init_secondary_core() {
+  CNTP_CTL_EL0.write(CNTP_CTL_EL0::ENABLE::CLEAR + CNTP_CTL_EL0::IMASK::SET);
+  irq_manager().enable(ARM_NS_PHYSICAL_TIMER);
+}
+
+/// Return a reference to the generic timer.
+#[allow(dead_code)]
+pub fn generic_timer() -> &'static impl time::interface::TimeManager {
   &TIME_MANAGER
 }
 
@@ -66,56 +112,43 @@ impl time::interface::TimeManager for GenericTimer {
   }
 
   fn uptime(&self) -> Duration {
-    let current_count: u64 = self.read_cntpct() * NS_PER_S;
+    let current_count = self.read_cntpct();
     let frq: u64 = CNTFRQ_EL0.get() as u64;
 
-    Duration::from_nanos(current_count / frq)
+    // Split, so the multiplication can't overflow. The remainder is below the
+    // 32 bit frequency.
+    let secs = current_count / frq;
+    let subsec_nanos = (current_count % frq) * NS_PER_S / frq;
+    Duration::new(secs, subsec_nanos as u32)
   }
 
   fn spin_for(&self, duration: Duration) {
-    // Instantly return on zero.
-    if duration.as_nanos() == 0 {
-      return;
-    }
-
-    // Calculate the register compare value.
-    let frq = CNTFRQ_EL0.get();
-    let x = match frq.checked_mul(duration.as_nanos() as u64) {
-      None => {
-        warn!("Spin duration too long, skipping");
-        return;
-      },
-      Some(val) => val,
-    };
-    let tval = x / NS_PER_S;
-
-    // Check if it is within supported bounds.
-    let warn: Option<&str> = if tval == 0 {
-      Some("smaller")
-    // The upper 32 bits of CNTP_TVAL_EL0 are reserved.
-    } else if tval > u32::max_value().into() {
-      Some("bigger")
-    } else {
-      None
-    };
-
-    if let Some(w) = warn {
-      warn!("Spin duration {} than architecturally supported, skipping", w);
-      return;
-    }
+    let deadline = self.read_cntpct().saturating_add(self.uptime_to_count(duration));
 
-    // Set the compare value register.
-    CNTP_TVAL_EL0.set(tval);
-
-    // Kick off the counting.
-
-    // Disable timer interrupt.
-    CNTP_CTL_EL0.modify(CNTP_CTL_EL0::ENABLE::SET + CNTP_CTL_EL0::IMASK::SET);
+    while self.read_cntpct() < deadline {}
+  }
 
-    // ISTATUS will be '1' when cval ticks have passed. Busy-check it.
-    while !CNTP_CTL_EL0.matches_all(CNTP_CTL_EL0::ISTATUS::SET) {}
+  fn sleep_until(&self, deadline: Instant) {
+    let deadline = self.uptime_to_count(deadline.since_power_on());
+
+    // The compare value is 64 bits wide, any deadline fits. cortex-a has no
+    // accessor for it.
+    unsafe { asm!("msr cntp_cval_el0, {}", in(reg) deadline, options(nostack)) };
+    CNTP_CTL_EL0.write(CNTP_CTL_EL0::ENABLE::SET + CNTP_CTL_EL0::IMASK::CLEAR);
+
+    loop {
+      // Check and sleep with IRQs masked. An IRQ arriving in between still
+      // wakes up wfi, and is taken once they are restored.
+      let saved = cpu::local_irq_mask_save();
+      if self.read_cntpct() >= deadline {
+        cpu::local_irq_restore(saved);
+        break;
+      }
+
+      cpu::wait_for_interrupt();
+      cpu::local_irq_restore(saved);
+    }
 
-    // Disable counting again.
-    CNTP_CTL_EL0.modify(CNTP_CTL_EL0::ENABLE::CLEAR);
+    CNTP_CTL_EL0.write(CNTP_CTL_EL0::ENABLE::CLEAR + CNTP_CTL_EL0::IMASK::SET);
   }
 }
@@ -1,5 +1,14 @@
 pub mod alloc;
 pub mod console;
 pub mod cpu;
+pub mod devicetree;
+pub mod exception;
 pub mod framebuffer;
+#[cfg(feature = "bsp_rpi4")]
+pub mod gicv2;
+#[cfg(feature = "bsp_rpi3")]
+pub mod interrupt_controller;
 pub mod mailbox;
+pub mod memory;
+pub mod power;
+pub mod system_timer;
@@ -1,51 +1,333 @@
+//! The kernel heap.
+//!
+//! A `linked_list_allocator` heap behind a lock, with statistics on top. The
+//! allocator keeps its free list to itself, so the free blocks are found by
+//! probing: the largest block is the largest allocation that succeeds.
+//!
+//! With the `debug_heap` feature, allocations are checked, see `debug`.
+
+#[cfg(feature = "debug_heap")]
+mod debug;
+
 use core::alloc::{GlobalAlloc, Layout};
-use core::cell::RefCell;
+use core::mem;
 use core::ptr::{self, NonNull};
+use core::time::Duration;
 
-use bare_metal::Mutex;
 use linked_list_allocator::Heap;
 
-use crate::cpu::free;
+use crate::cpu::SpinLock;
+use crate::time::timers::{self, TimerHandle};
+use crate::{info, warn};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Free blocks are multiples of this, and at least two of it.
+const BLOCK_GRANULE: usize = mem::size_of::<usize>();
+
+/// Free blocks listed by `dump`, largest first. The rest is only counted.
+const MAX_DUMPED_BLOCKS: usize = 32;
+
+struct HeapState {
+  heap: Heap,
+  peak: usize,
+  live_allocations: usize,
+  allocs: u64,
+  frees: u64,
+  /// `allocs` and `frees` at the end of the frame before the last one.
+  frame_start: (u64, u64),
+  allocs_last_frame: u64,
+  frees_last_frame: u64,
+  #[cfg(feature = "debug_heap")]
+  debug: debug::DebugState,
+}
+
+/// A free block taken out while walking the free blocks. Links to the one
+/// taken before.
+struct TakenBlock {
+  size: usize,
+  next: Option<NonNull<TakenBlock>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+pub struct SharedHeap(SpinLock<HeapState>);
+
+/// A snapshot of the heap. Sizes include the allocator's rounding.
+#[derive(Copy, Clone, Debug)]
+pub struct HeapStats {
+  pub size: usize,
+  pub in_use: usize,
+  /// The most `in_use` ever was.
+  pub peak: usize,
+  pub live_allocations: usize,
+  pub allocs_last_frame: u64,
+  pub frees_last_frame: u64,
+  /// The largest allocation that would succeed, at 8 byte alignment.
+  pub largest_free_block: usize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+#[global_allocator]
+pub static ALLOCATOR: SharedHeap = SharedHeap::empty();
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
 
-pub struct SharedHeap(Mutex<RefCell<Heap>>);
+fn block_layout(size: usize) -> Layout {
+  Layout::from_size_align(size, BLOCK_GRANULE).unwrap()
+}
+
+impl HeapState {
+  fn alloc(&mut self, layout: Layout) -> *mut u8 {
+    match self.heap.allocate_first_fit(layout) {
+      Ok(ptr) => {
+        self.allocs += 1;
+        self.live_allocations += 1;
+        self.peak = self.peak.max(self.heap.used());
+        ptr.as_ptr()
+      },
+      Err(()) => ptr::null_mut(),
+    }
+  }
+
+  unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+    self.heap.deallocate(NonNull::new_unchecked(ptr), layout);
+    self.frees += 1;
+    self.live_allocations -= 1;
+  }
+
+  fn fits(&mut self, size: usize) -> bool {
+    match self.heap.allocate_first_fit(block_layout(size)) {
+      Ok(ptr) => {
+        unsafe { self.heap.deallocate(ptr, block_layout(size)) };
+        true
+      },
+      Err(()) => false,
+    }
+  }
+
+  /// Whether an allocation of `n` or `n + 1` granules succeeds.
+  ///
+  /// Unlike `fits` alone, this holds for all sizes below the largest block: the
+  /// allocator refuses to leave a remainder too small for a free block, so a
+  /// block of 6 granules takes 4 and 6, but not 5.
+  fn fits_granules(&mut self, n: usize) -> bool {
+    self.fits(n * BLOCK_GRANULE) || self.fits((n + 1) * BLOCK_GRANULE)
+  }
+
+  /// Binary search for the largest allocation that succeeds.
+  fn largest_free_block(&mut self) -> usize {
+    let min_granules = 2;
+    if !self.fits_granules(min_granules) {
+      return 0;
+    }
+
+    let (mut fits, mut too_big) = (min_granules, self.heap.free() / BLOCK_GRANULE + 1);
+    while too_big - fits > 1 {
+      let mid = fits + (too_big - fits) / 2;
+      if self.fits_granules(mid) {
+        fits = mid;
+      } else {
+        too_big = mid;
+      }
+    }
+
+    if self.fits(fits * BLOCK_GRANULE) {
+      fits * BLOCK_GRANULE
+    } else {
+      (fits + 1) * BLOCK_GRANULE
+    }
+  }
+
+  /// Call `f` with the address and size of each free block, largest first.
+  ///
+  /// Takes out the largest block until there is none left, then returns them
+  /// all. Allocating exactly a block's size uses it up, so each round finds a
+  /// different block, and returning them leaves the free list as it was.
+  ///
+  /// Stops with an error if a block would only be taken in part, as the rest
+  /// would show up as a block of its own.
+  fn for_each_free_block(&mut self, mut f: impl FnMut(usize, usize)) -> Result<(), &'static str> {
+    let mut taken: Option<NonNull<TakenBlock>> = None;
+    let mut result = Ok(());
+
+    loop {
+      let size = self.largest_free_block();
+      if size == 0 {
+        break;
+      }
+      if self.fits(size + BLOCK_GRANULE) || self.fits(size + 2 * BLOCK_GRANULE) {
+        result = Err("Found a free block only in part");
+        break;
+      }
+
+      let block = match self.heap.allocate_first_fit(block_layout(size)) {
+        Ok(block) => block.cast::<TakenBlock>(),
+        Err(()) => break,
+      };
+      f(block.as_ptr() as usize, size);
+
+      unsafe { ptr::write(block.as_ptr(), TakenBlock { size, next: taken }) };
+      taken = Some(block);
+    }
+
+    while let Some(block) = taken {
+      let TakenBlock { size, next } = unsafe { ptr::read(block.as_ptr()) };
+      unsafe { self.heap.deallocate(block.cast(), block_layout(size)) };
+      taken = next;
+    }
+
+    result
+  }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
 
 impl SharedHeap {
   pub const fn empty() -> SharedHeap {
-    SharedHeap(Mutex::new(RefCell::new(Heap::empty())))
+    SharedHeap(SpinLock::new(
+      "heap",
+      HeapState {
+        heap: Heap::empty(),
+        peak: 0,
+        live_allocations: 0,
+        allocs: 0,
+        frees: 0,
+        frame_start: (0, 0),
+        allocs_last_frame: 0,
+        frees_last_frame: 0,
+        #[cfg(feature = "debug_heap")]
+        debug: debug::DebugState::new(),
+      },
+    ))
   }
 
   pub fn init(&self, start_addr: usize, size: usize) {
-    free(|cs| unsafe {
-      if let Ok(mut lock) = self.0.borrow(*cs).try_borrow_mut() {
-        lock.init(start_addr, size);
-      } else {
-        panic!("Failed to allocate heap!");
+    unsafe { self.0.lock().heap.init(start_addr, size) }
+  }
+
+  /// Probes for the largest free block, which takes a few allocations.
+  pub fn stats(&self) -> HeapStats {
+    let mut state = self.0.lock();
+
+    HeapStats {
+      size: state.heap.size(),
+      in_use: state.heap.used(),
+      peak: state.peak,
+      live_allocations: state.live_allocations,
+      allocs_last_frame: state.allocs_last_frame,
+      frees_last_frame: state.frees_last_frame,
+      largest_free_block: state.largest_free_block(),
+    }
+  }
+
+  /// Close the frame for the per-frame allocation counts.
+  pub fn end_frame(&self) {
+    let mut state = self.0.lock();
+
+    let (allocs, frees) = state.frame_start;
+    state.allocs_last_frame = state.allocs - allocs;
+    state.frees_last_frame = state.frees - frees;
+    state.frame_start = (state.allocs, state.frees);
+  }
+
+  /// Check the red zones of all live allocations. Panics on the first broken
+  /// one.
+  #[cfg(feature = "debug_heap")]
+  pub fn verify(&self) {
+    // Panicking may allocate, don't hold the lock.
+    let result = debug::verify(&self.0.lock());
+    if let Err((violation, address, layout)) = result {
+      panic!("Heap: {}, {:?} at {:#x}", violation, layout, address);
+    }
+  }
+
+  /// Log the statistics and the free blocks.
+  pub fn dump(&self) {
+    #[cfg(feature = "debug_heap")]
+    self.verify();
+
+    let stats = self.stats();
+    info!(
+      "Heap: {} of {} KiB in use, peak {} KiB, {} allocations",
+      stats.in_use >> 10,
+      stats.size >> 10,
+      stats.peak >> 10,
+      stats.live_allocations
+    );
+    info!(
+      "      Last frame: {} allocs, {} frees",
+      stats.allocs_last_frame, stats.frees_last_frame
+    );
+
+    // Logging allocates, so collect first.
+    let mut blocks = [(0, 0); MAX_DUMPED_BLOCKS];
+    let (mut count, mut total) = (0, 0);
+    let result = self.0.lock().for_each_free_block(|address, size| {
+      if count < MAX_DUMPED_BLOCKS {
+        blocks[count] = (address, size);
       }
+      count += 1;
+      total += size;
     });
+    if let Err(err) = result {
+      warn!("      Free blocks incomplete: {}", err);
+    }
+
+    info!("      {} free blocks, {} KiB:", count, total >> 10);
+    for (address, size) in &blocks[..count.min(MAX_DUMPED_BLOCKS)] {
+      info!(
+        "      {:#010x} - {:#010x} {:>10} bytes",
+        address,
+        address + size - 1,
+        size
+      );
+    }
+    if count > MAX_DUMPED_BLOCKS {
+      info!("      ... {} more", count - MAX_DUMPED_BLOCKS);
+    }
   }
 }
 
+/// Dump the heap every `interval`.
+pub fn start_periodic_dump(interval: Duration) -> TimerHandle {
+  timers::start_periodic(interval, || ALLOCATOR.dump())
+}
+
 unsafe impl GlobalAlloc for SharedHeap {
+  #[cfg(not(feature = "debug_heap"))]
   unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-    free(|cs| {
-      if let Ok(mut lock) = self.0.borrow(*cs).try_borrow_mut() {
-        lock
-          .allocate_first_fit(layout)
-          .ok()
-          .map_or(ptr::null_mut(), |a| a.as_ptr())
-      } else {
-        panic!("Failed to get lock on shared heap. Already borrowed?");
-      }
-    })
+    self.0.lock().alloc(layout)
   }
 
+  #[cfg(not(feature = "debug_heap"))]
   unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-    free(|cs| {
-      self
-        .0
-        .borrow_ref_mut(*cs)
-        .deallocate(NonNull::new_unchecked(ptr), layout)
-    })
+    self.0.lock().dealloc(ptr, layout)
+  }
+
+  #[cfg(feature = "debug_heap")]
+  unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    debug::alloc(&mut self.0.lock(), layout)
+  }
+
+  #[cfg(feature = "debug_heap")]
+  unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    // Panicking may allocate, don't hold the lock.
+    let result = debug::dealloc(&mut self.0.lock(), ptr, layout);
+    if let Err(violation) = result {
+      panic!("Heap: {}, {:?} at {:#x}", violation, layout, ptr as usize);
+    }
   }
 }
 
@@ -53,6 +335,3 @@ unsafe impl GlobalAlloc for SharedHeap {
 fn on_oom(layout: Layout) -> ! {
   panic!("Failed to allocate: {:?}", layout)
 }
-
-#[global_allocator]
-pub static ALLOCATOR: SharedHeap = SharedHeap::empty();
@@ -0,0 +1,235 @@
+//! Heap checks, for the `debug_heap` feature.
+//!
+//! Each allocation is laid out as header, red zone, the caller's memory and
+//! another red zone:
+//!
+//! ```text
+//! | Header | FD FD .. FD | caller's memory | FD FD .. FD |
+//! ```
+//!
+//! The red zones must be intact when the memory is freed. Fresh memory is
+//! filled with `CD`, freed memory with `DD`, so reads of either stand out. The
+//! headers link all live allocations, for checking them on demand, and the last
+//! frees are remembered to catch freeing twice.
+
+use core::alloc::Layout;
+use core::{fmt, mem, ptr};
+
+use super::HeapState;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+const RED_ZONE_SIZE: usize = 16;
+const RED_ZONE_BYTE: u8 = 0xFD;
+const ALLOC_POISON: u8 = 0xCD;
+const FREE_POISON: u8 = 0xDD;
+
+/// Frees remembered for double-free detection.
+const RECENT_FREES: usize = 64;
+
+/// Precedes the front red zone. Links are addresses of other headers, 0 for
+/// none.
+struct Header {
+  layout: Layout,
+  prev: usize,
+  next: usize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Bookkeeping of the checks, part of the heap state.
+pub(super) struct DebugState {
+  /// The most recent live allocation's header, 0 for none.
+  live: usize,
+  /// Addresses handed out and freed since. 0 for unused slots.
+  recent_frees: [usize; RECENT_FREES],
+  next_free_slot: usize,
+}
+
+/// A failed check.
+pub(super) enum Violation {
+  DoubleFree,
+  /// Freed with a different layout than it was allocated with.
+  LayoutMismatch(Layout),
+  FrontRedZone,
+  BackRedZone,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// Header and front red zone, keeping the caller's memory aligned.
+fn front_size(layout: &Layout) -> usize {
+  let size = mem::size_of::<Header>() + RED_ZONE_SIZE;
+  (size + layout.align() - 1) & !(layout.align() - 1)
+}
+
+/// The layout of the whole block around an allocation of `layout`.
+fn block_layout(layout: &Layout) -> Option<Layout> {
+  let size = front_size(layout)
+    .checked_add(layout.size())?
+    .checked_add(RED_ZONE_SIZE)?;
+  Layout::from_size_align(size, layout.align().max(mem::align_of::<Header>())).ok()
+}
+
+fn fill(start: usize, len: usize, byte: u8) {
+  unsafe { ptr::write_bytes(start as *mut u8, byte, len) }
+}
+
+fn is_filled(start: usize, len: usize, byte: u8) -> bool {
+  (start..start + len).all(|address| unsafe { ptr::read(address as *const u8) } == byte)
+}
+
+fn header<'a>(address: usize) -> &'a mut Header {
+  unsafe { &mut *(address as *mut Header) }
+}
+
+/// Check the red zones of the allocation with its header at `block`.
+fn check_red_zones(block: usize) -> Result<(), Violation> {
+  let layout = header(block).layout;
+  let user = block + front_size(&layout);
+
+  let front = block + mem::size_of::<Header>();
+  if !is_filled(front, user - front, RED_ZONE_BYTE) {
+    return Err(Violation::FrontRedZone);
+  }
+  if !is_filled(user + layout.size(), RED_ZONE_SIZE, RED_ZONE_BYTE) {
+    return Err(Violation::BackRedZone);
+  }
+  Ok(())
+}
+
+impl DebugState {
+  fn link(&mut self, block: usize) {
+    header(block).next = self.live;
+    if self.live != 0 {
+      header(self.live).prev = block;
+    }
+    self.live = block;
+  }
+
+  fn unlink(&mut self, block: usize) {
+    let Header { prev, next, .. } = *header(block);
+    if prev != 0 {
+      header(prev).next = next;
+    } else {
+      self.live = next;
+    }
+    if next != 0 {
+      header(next).prev = prev;
+    }
+  }
+
+  /// Forget a free of `address`, which was handed out again.
+  fn forget_free(&mut self, address: usize) {
+    for slot in self.recent_frees.iter_mut().filter(|slot| **slot == address) {
+      *slot = 0;
+    }
+  }
+
+  fn remember_free(&mut self, address: usize) {
+    self.recent_frees[self.next_free_slot] = address;
+    self.next_free_slot = (self.next_free_slot + 1) % RECENT_FREES;
+  }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl DebugState {
+  pub(super) const fn new() -> Self {
+    Self {
+      live: 0,
+      recent_frees: [0; RECENT_FREES],
+      next_free_slot: 0,
+    }
+  }
+}
+
+impl fmt::Display for Violation {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Violation::DoubleFree => write!(f, "Double free"),
+      Violation::LayoutMismatch(allocated) => write!(f, "Freed with another layout than {:?}", allocated),
+      Violation::FrontRedZone => write!(f, "Write before the start"),
+      Violation::BackRedZone => write!(f, "Write past the end"),
+    }
+  }
+}
+
+/// Allocate `layout` with header and red zones around it.
+pub(super) fn alloc(state: &mut HeapState, layout: Layout) -> *mut u8 {
+  let outer = match block_layout(&layout) {
+    Some(outer) => outer,
+    None => return ptr::null_mut(),
+  };
+  let block = state.alloc(outer) as usize;
+  if block == 0 {
+    return ptr::null_mut();
+  }
+
+  let user = block + front_size(&layout);
+  unsafe {
+    ptr::write(
+      block as *mut Header,
+      Header {
+        layout,
+        prev: 0,
+        next: 0,
+      },
+    )
+  };
+  let front = block + mem::size_of::<Header>();
+  fill(front, user - front, RED_ZONE_BYTE);
+  fill(user, layout.size(), ALLOC_POISON);
+  fill(user + layout.size(), RED_ZONE_SIZE, RED_ZONE_BYTE);
+
+  state.debug.link(block);
+  state.debug.forget_free(user);
+  user as *mut u8
+}
+
+/// Check and free an allocation from `alloc`. Nothing is freed if a check
+/// fails.
+pub(super) fn dealloc(state: &mut HeapState, ptr: *mut u8, layout: Layout) -> Result<(), Violation> {
+  let user = ptr as usize;
+  if state.debug.recent_frees.contains(&user) {
+    return Err(Violation::DoubleFree);
+  }
+
+  let block = user - front_size(&layout);
+  let allocated = header(block).layout;
+  if allocated != layout {
+    return Err(Violation::LayoutMismatch(allocated));
+  }
+  check_red_zones(block)?;
+
+  state.debug.unlink(block);
+  state.debug.remember_free(user);
+
+  // Block layouts are valid, they were allocated.
+  let outer = block_layout(&layout).unwrap();
+  fill(block, outer.size(), FREE_POISON);
+  unsafe { state.dealloc(block as *mut u8, outer) };
+  Ok(())
+}
+
+/// Check the red zones of all live allocations. Returns the first broken
+/// one's address and layout.
+pub(super) fn verify(state: &HeapState) -> Result<(), (Violation, usize, Layout)> {
+  let mut block = state.debug.live;
+  while block != 0 {
+    let layout = header(block).layout;
+    if let Err(violation) = check_red_zones(block) {
+      return Err((violation, block + front_size(&layout), layout));
+    }
+    block = header(block).next;
+  }
+  Ok(())
+}
@@ -4,12 +4,10 @@
 
 //! Console BSP
 
-use core::cell::RefCell;
 use core::fmt::{self, Arguments, Result};
 
-use bare_metal::Mutex;
-
-use crate::cpu::free;
+use super::devicetree::pl011_uart_base;
+use crate::cpu::SpinLock;
 use crate::io::console;
 
 /// Global QEMU std out handler
@@ -21,13 +19,16 @@ struct QEMUOutputInner;
 /// QEMU Output Controller
 struct QEMUOutput {
   /// STD Out Lock
-  inner: Mutex<RefCell<QEMUOutputInner>>,
+  inner: SpinLock<QEMUOutputInner>,
 }
 
+/// Lock-free QEMU Output, for when the lock holder may never let go.
+struct PanicOutput;
+
 impl QEMUOutput {
   pub const fn new() -> Self {
     Self {
-      inner: Mutex::new(RefCell::new(QEMUOutputInner::new())),
+      inner: SpinLock::new("console", QEMUOutputInner::new()),
     }
   }
 }
@@ -39,7 +40,7 @@ impl QEMUOutputInner {
 
   fn write_char(&mut self, c: char) {
     unsafe {
-      core::ptr::write_volatile(0x3F20_1000 as *mut u8, c as u8);
+      core::ptr::write_volatile(pl011_uart_base() as *mut u8, c as u8);
     }
   }
 }
@@ -54,23 +55,28 @@ impl fmt::Write for QEMUOutputInner {
 
 impl console::interface::Write for QEMUOutput {
   fn write_fmt(&self, args: Arguments) -> Result {
-    free(|cs| {
-      if let Err(err) = fmt::Write::write_fmt(&mut *self.inner.borrow_ref_mut(*cs), args) {
-        panic!("{}", err);
-      }
-    });
+    if let Err(err) = fmt::Write::write_fmt(&mut *self.inner.lock(), args) {
+      panic!("{}", err);
+    }
 
     Ok(())
   }
 }
 
+impl console::interface::Write for PanicOutput {
+  fn write_fmt(&self, args: Arguments) -> Result {
+    fmt::Write::write_fmt(&mut QEMUOutputInner::new(), args)
+  }
+}
+
 /// Return a reference to the console.
 pub fn console() -> &'static impl console::interface::Write {
   &QEMU_OUTPUT
 }
 
 /// Returns a new reference to the console, should only be used when something
-/// is panicking.
+/// is panicking. Bypasses the console lock, which might be held by whoever
+/// panicked.
 pub fn new_console() -> impl console::interface::Write {
-  QEMUOutput::new()
+  PanicOutput
 }
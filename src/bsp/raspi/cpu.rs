@@ -2,3 +2,24 @@
 #[no_mangle]
 #[link_section = ".text._start_arguments"]
 pub static BOOT_CORE_ID: u64 = 0;
+
+/// Number of cores on the SoC.
+pub const NUM_CORES: usize = 4;
+
+/// The firmware's armstub parks cores 1-3 polling these addresses, one u64 per
+/// core, until a non-zero entry point shows up.
+const SPIN_TABLE_BASE: usize = 0xd8;
+
+/// Hand the entry point to a core waiting in the firmware spin table.
+///
+/// # Safety
+///
+/// - `entry` must be a valid entry point for a core running with the MMU off.
+pub unsafe fn release_secondary_core(core_id: usize, entry: usize) {
+  let release_addr = (SPIN_TABLE_BASE + core_id * 8) as *mut u64;
+  core::ptr::write_volatile(release_addr, entry as u64);
+
+  // The waiting core reads with its caches off.
+  crate::cpu::clean_dcache_line(release_addr as usize);
+  crate::cpu::send_event();
+}
@@ -0,0 +1,194 @@
+//! Hardware discovery through the device tree.
+//!
+//! The firmware passes a flattened device tree to the kernel. Peripheral
+//! addresses found in it replace the defaults from `memory::map::mmio`, which
+//! stay in use when the firmware didn't provide one.
+
+use core::ops::RangeInclusive;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use game_console::fdt::Fdt;
+
+use super::memory::map::mmio;
+use crate::mem::kernel_range_inclusive;
+use crate::{info, warn};
+
+/// Address of the validated blob, or 0.
+static DEVICE_TREE: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(feature = "bsp_rpi3")]
+static INTERRUPT_CONTROLLER_BASE: AtomicUsize = AtomicUsize::new(mmio::INTERRUPT_CONTROLLER_START);
+#[cfg(feature = "bsp_rpi3")]
+static LOCAL_INTERRUPT_CONTROLLER_BASE: AtomicUsize = AtomicUsize::new(mmio::LOCAL_INTERRUPT_CONTROLLER_START);
+#[cfg(feature = "bsp_rpi4")]
+static GICD_BASE: AtomicUsize = AtomicUsize::new(mmio::GICD_START);
+#[cfg(feature = "bsp_rpi4")]
+static GICC_BASE: AtomicUsize = AtomicUsize::new(mmio::GICC_START);
+static MAILBOX_BASE: AtomicUsize = AtomicUsize::new(mmio::MAILBOX_START);
+static PL011_UART_BASE: AtomicUsize = AtomicUsize::new(mmio::PL011_UART_START);
+static PM_BASE: AtomicUsize = AtomicUsize::new(mmio::PM_START);
+static SYSTEM_TIMER_BASE: AtomicUsize = AtomicUsize::new(mmio::SYSTEM_TIMER_START);
+
+/// Look up the first device compatible with `compatible` and use its MMIO
+/// base, if it lies in the mapped device range.
+fn discover(fdt: &Fdt, name: &str, compatible: &str, base: &AtomicUsize) {
+  discover_reg(fdt, name, compatible, 0, base)
+}
+
+/// Like `discover`, for devices with several register blocks. Uses the
+/// `index`th entry of `reg`.
+fn discover_reg(fdt: &Fdt, name: &str, compatible: &str, index: usize, base: &AtomicUsize) {
+  let address = fdt
+    .find_compatible(compatible)
+    .and_then(|node| node.reg().nth(index))
+    .and_then(|reg| fdt.translate_soc_address(reg.address));
+
+  match address {
+    Some(address) if (mmio::START..=mmio::END_INCLUSIVE).contains(&(address as usize)) => {
+      base.store(address as usize, Ordering::Relaxed);
+      info!("      {} at {:#010x}", name, address);
+    },
+    Some(address) => warn!(
+      "{} at {:#x} is outside the MMIO range, using {:#010x}",
+      name,
+      address,
+      base.load(Ordering::Relaxed)
+    ),
+    None => warn!(
+      "{} not in the device tree, using {:#010x}",
+      name,
+      base.load(Ordering::Relaxed)
+    ),
+  }
+}
+
+/// Validate the device tree at `dtb_addr` and discover the peripherals from it.
+/// Must run before any other core starts.
+///
+/// # Safety
+///
+/// - `dtb_addr` must be the value the firmware passed in x0.
+pub unsafe fn init(dtb_addr: usize) {
+  let fdt = match Fdt::from_addr(dtb_addr) {
+    Ok(fdt) => fdt,
+    Err(err) => {
+      warn!("No usable device tree at {:#x} ({}), using defaults", dtb_addr, err);
+      return;
+    },
+  };
+
+  let dtb_range = dtb_addr..=dtb_addr + fdt.total_size() - 1;
+  let kernel_range = kernel_range_inclusive();
+  if dtb_range.start() <= kernel_range.end() && kernel_range.start() <= dtb_range.end() {
+    warn!(
+      "Device tree at {:#x} overlaps the kernel's memory, using defaults",
+      dtb_addr
+    );
+    return;
+  }
+
+  info!("Device tree at {:#x}, {} bytes", dtb_addr, fdt.total_size());
+  for range in fdt.memory_ranges() {
+    info!(
+      "      Memory {:#010x} - {:#010x}",
+      range.address,
+      range.address + range.size.saturating_sub(1)
+    );
+  }
+
+  #[cfg(feature = "bsp_rpi3")]
+  {
+    discover(
+      &fdt,
+      "Interrupt controller",
+      "brcm,bcm2836-armctrl-ic",
+      &INTERRUPT_CONTROLLER_BASE,
+    );
+    discover(
+      &fdt,
+      "Local interrupt controller",
+      "brcm,bcm2836-l1-intc",
+      &LOCAL_INTERRUPT_CONTROLLER_BASE,
+    );
+  }
+  #[cfg(feature = "bsp_rpi4")]
+  {
+    discover_reg(&fdt, "GIC distributor", "arm,gic-400", 0, &GICD_BASE);
+    discover_reg(&fdt, "GIC CPU interface", "arm,gic-400", 1, &GICC_BASE);
+  }
+  discover(&fdt, "Mailbox", "brcm,bcm2835-mbox", &MAILBOX_BASE);
+  discover(&fdt, "PL011 UART", "arm,pl011", &PL011_UART_BASE);
+  discover(&fdt, "Power management", "brcm,bcm2835-pm-wdt", &PM_BASE);
+  discover(&fdt, "System timer", "brcm,bcm2835-system-timer", &SYSTEM_TIMER_BASE);
+
+  DEVICE_TREE.store(dtb_addr, Ordering::Release);
+}
+
+/// The device tree, if the firmware passed a usable one.
+pub fn device_tree() -> Option<Fdt<'static>> {
+  match DEVICE_TREE.load(Ordering::Acquire) {
+    0 => None,
+    // Validated in `init`. `init_heap` keeps the heap and the page frames
+    // clear of it.
+    addr => unsafe { Fdt::from_addr(addr).ok() },
+  }
+}
+
+/// The memory the device tree's `/memreserve/` entries keep from the kernel.
+pub fn mem_reservations() -> impl Iterator<Item = RangeInclusive<usize>> {
+  device_tree()
+    .into_iter()
+    .flat_map(|fdt| fdt.mem_reservations())
+    .map(|entry| entry.address as usize..=entry.address.saturating_add(entry.size - 1) as usize)
+}
+
+/// The memory the device tree takes, if the firmware passed a usable one.
+pub fn device_tree_range_inclusive() -> Option<RangeInclusive<usize>> {
+  let fdt = device_tree()?;
+  let start = DEVICE_TREE.load(Ordering::Acquire);
+  Some(start..=start + fdt.total_size() - 1)
+}
+
+/// MMIO base of the legacy peripheral interrupt controller.
+#[cfg(feature = "bsp_rpi3")]
+pub fn interrupt_controller_base() -> usize {
+  INTERRUPT_CONTROLLER_BASE.load(Ordering::Relaxed)
+}
+
+/// MMIO base of the ARM-local interrupt controller.
+#[cfg(feature = "bsp_rpi3")]
+pub fn local_interrupt_controller_base() -> usize {
+  LOCAL_INTERRUPT_CONTROLLER_BASE.load(Ordering::Relaxed)
+}
+
+/// MMIO base of the GIC-400 distributor.
+#[cfg(feature = "bsp_rpi4")]
+pub fn gicd_base() -> usize {
+  GICD_BASE.load(Ordering::Relaxed)
+}
+
+/// MMIO base of the GIC-400 CPU interface.
+#[cfg(feature = "bsp_rpi4")]
+pub fn gicc_base() -> usize {
+  GICC_BASE.load(Ordering::Relaxed)
+}
+
+/// MMIO base of the VideoCore mailbox.
+pub fn mailbox_base() -> usize {
+  MAILBOX_BASE.load(Ordering::Relaxed)
+}
+
+/// MMIO base of the PL011 UART.
+pub fn pl011_uart_base() -> usize {
+  PL011_UART_BASE.load(Ordering::Relaxed)
+}
+
+/// MMIO base of the power management block, which holds the watchdog.
+pub fn pm_base() -> usize {
+  PM_BASE.load(Ordering::Relaxed)
+}
+
+/// MMIO base of the BCM system timer.
+pub fn system_timer_base() -> usize {
+  SYSTEM_TIMER_BASE.load(Ordering::Relaxed)
+}
@@ -0,0 +1,36 @@
+//! BSP exception handling.
+
+/// Asynchronous exception handling.
+pub mod asynchronous {
+  #[cfg(feature = "bsp_rpi4")]
+  pub use super::super::gicv2::{init, init_secondary_core, irq_manager, IRQNumber};
+  #[cfg(feature = "bsp_rpi3")]
+  pub use super::super::interrupt_controller::{init, init_secondary_core, irq_manager, IRQNumber};
+
+  /// The board's IRQ numbers.
+  pub mod irq_map {
+    use super::IRQNumber;
+
+    /// Non-secure physical timer of the executing core.
+    #[cfg(feature = "bsp_rpi3")]
+    pub const ARM_NS_PHYSICAL_TIMER: IRQNumber = IRQNumber::Local(1);
+    #[cfg(feature = "bsp_rpi4")]
+    pub const ARM_NS_PHYSICAL_TIMER: IRQNumber = IRQNumber::new(30);
+
+    /// System timer compare channels 1 and 3. The GPU uses 0 and 2.
+    #[cfg(feature = "bsp_rpi3")]
+    pub const SYSTEM_TIMER_C1: IRQNumber = IRQNumber::Peripheral(1);
+    #[cfg(feature = "bsp_rpi3")]
+    pub const SYSTEM_TIMER_C3: IRQNumber = IRQNumber::Peripheral(3);
+    #[cfg(feature = "bsp_rpi4")]
+    pub const SYSTEM_TIMER_C1: IRQNumber = IRQNumber::new(97);
+    #[cfg(feature = "bsp_rpi4")]
+    pub const SYSTEM_TIMER_C3: IRQNumber = IRQNumber::new(99);
+
+    /// ARM mailbox 0, data available.
+    #[cfg(feature = "bsp_rpi3")]
+    pub const MAILBOX: IRQNumber = IRQNumber::Basic(1);
+    #[cfg(feature = "bsp_rpi4")]
+    pub const MAILBOX: IRQNumber = IRQNumber::new(65);
+  }
+}
@@ -3,7 +3,7 @@ use embedded_graphics::pixelcolor::Rgb888;
 use embedded_graphics::prelude::{OriginDimensions, RgbColor, Size};
 use embedded_graphics::Pixel;
 
-use crate::warn;
+use crate::{profile_zone, warn};
 
 pub struct FrameBuffer {
   pub bytes_per_pixel: usize,
@@ -54,6 +54,8 @@ impl DrawTarget for FrameBuffer {
   where
     I: IntoIterator<Item = embedded_graphics::Pixel<Self::Color>>,
   {
+    profile_zone!("draw_iter");
+
     for Pixel(coord, color) in pixels.into_iter() {
       let (x, y) = coord.into();
       let (x, y) = (x.min(self.width as i32), y.min(self.height as i32));
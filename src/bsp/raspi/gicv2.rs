@@ -0,0 +1,265 @@
+//! GIC-400 interrupt controller driver.
+//!
+//! The Pi 4 routes interrupts through a GICv2. The distributor is shared by
+//! all cores: it holds the enable, priority and target of each interrupt. Each
+//! core has its own CPU interface, through which it acknowledges interrupts.
+//!
+//! Interrupt IDs (INTIDs) 0-15 are software generated, 16-31 private per-core
+//! peripherals like the core timers, and from 32 on shared peripherals. The
+//! VideoCore peripheral IRQs start at INTID 96.
+//!
+//! # Resources
+//!
+//! - ARM Generic Interrupt Controller Architecture Specification v2
+//! - ARM CoreLink GIC-400 Technical Reference Manual
+
+use core::fmt;
+
+use tock_registers::interfaces::{Readable, Writeable};
+use tock_registers::register_structs;
+use tock_registers::registers::{ReadOnly, ReadWrite, WriteOnly};
+
+use super::cpu::BOOT_CORE_ID;
+use super::devicetree::{gicc_base, gicd_base};
+use crate::cpu::SpinLock;
+use crate::exception::asynchronous::{interface, IRQDescriptor};
+use crate::info;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+register_structs! {
+  #[allow(non_snake_case)]
+  DistributorRegisterBlock {
+    (0x000 => CTLR: ReadWrite<u32>),
+    (0x004 => TYPER: ReadOnly<u32>),
+    (0x008 => _reserved1),
+    (0x100 => ISENABLER: [ReadWrite<u32>; 32]),
+    (0x180 => ICENABLER: [ReadWrite<u32>; 32]),
+    (0x200 => _reserved2),
+    (0x400 => IPRIORITYR: [ReadWrite<u8>; 1024]),
+    (0x800 => ITARGETSR: [ReadWrite<u8>; 1024]),
+    (0xc00 => @END),
+  }
+}
+
+register_structs! {
+  #[allow(non_snake_case)]
+  CpuInterfaceRegisterBlock {
+    (0x000 => CTLR: ReadWrite<u32>),
+    (0x004 => PMR: ReadWrite<u32>),
+    (0x008 => BPR: ReadWrite<u32>),
+    (0x00c => IAR: ReadOnly<u32>),
+    (0x010 => EOIR: WriteOnly<u32>),
+    (0x014 => @END),
+  }
+}
+
+/// The GIC-400 of the BCM2711 implements 256 INTIDs.
+const NUM_IRQS: usize = 256;
+
+/// The first shared peripheral interrupt. Those below are banked per core.
+const FIRST_SPI: usize = 32;
+
+/// INTIDs from here on mean "no interrupt pending".
+const SPURIOUS_IRQ: u32 = 1020;
+
+const IAR_INTID_MASK: u32 = 0x3ff;
+
+/// Priority given to every interrupt by `init`. Lower values are more urgent.
+const DEFAULT_PRIORITY: u8 = 0xa0;
+
+/// Let interrupts of all priorities through the CPU interface.
+const PMR_ALL: u32 = 0xff;
+
+struct GICv2 {
+  handlers: SpinLock<[Option<IRQDescriptor>; NUM_IRQS]>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// An interrupt ID.
+#[derive(Copy, Clone)]
+pub struct IRQNumber(usize);
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static GIC: GICv2 = GICv2 {
+  handlers: SpinLock::new("irq handlers", [None; NUM_IRQS]),
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl GICv2 {
+  fn distributor(&self) -> &DistributorRegisterBlock {
+    unsafe { &*(gicd_base() as *const DistributorRegisterBlock) }
+  }
+
+  fn cpu_interface(&self) -> &CpuInterfaceRegisterBlock {
+    unsafe { &*(gicc_base() as *const CpuInterfaceRegisterBlock) }
+  }
+
+  fn dispatch(&self, irq: IRQNumber) {
+    // Copy the descriptor out, so handlers may use the controller themselves.
+    let descriptor = self.handlers.lock()[irq.0];
+
+    match descriptor {
+      Some(descriptor) => (descriptor.handler)(),
+      None => panic!("No handler registered for IRQ {}", irq),
+    }
+  }
+
+  /// Enable the CPU interface of the executing core.
+  fn init_cpu_interface(&self) {
+    let cpu_interface = self.cpu_interface();
+    cpu_interface.PMR.set(PMR_ALL);
+    cpu_interface.BPR.set(0);
+    cpu_interface.CTLR.set(1);
+  }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl IRQNumber {
+  /// The interrupt with ID `intid`.
+  pub const fn new(intid: usize) -> Self {
+    assert!(intid < NUM_IRQS, "INTID out of range");
+    Self(intid)
+  }
+}
+
+impl fmt::Display for IRQNumber {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "INTID {}", self.0)
+  }
+}
+
+/// Set the priority of `irq`. Lower values are more urgent. For private
+/// interrupts, this applies to the executing core only.
+#[allow(dead_code)]
+pub fn set_priority(irq: IRQNumber, priority: u8) {
+  GIC.distributor().IPRIORITYR[irq.0].set(priority);
+}
+
+/// Route the shared interrupt `irq` to the cores in `core_mask`, bit 0 being
+/// core 0. Private interrupts always go to their own core.
+#[allow(dead_code)]
+pub fn set_target_cores(irq: IRQNumber, core_mask: u8) -> Result<(), &'static str> {
+  if irq.0 < FIRST_SPI {
+    return Err("Private interrupts can't be retargeted");
+  }
+
+  GIC.distributor().ITARGETSR[irq.0].set(core_mask);
+  Ok(())
+}
+
+/// Start with all interrupts disabled, at the default priority and shared ones
+/// routed to the boot core. Enables the distributor and the boot core's CPU
+/// interface.
+///
+/// # Safety
+///
+/// - Must run once, on the boot core, before IRQs are unmasked anywhere.
+pub unsafe fn init() {
+  let distributor = GIC.distributor();
+  distributor.CTLR.set(0);
+
+  // ITLinesNumber, in units of 32 INTIDs.
+  let num_irqs = ((distributor.TYPER.get() as usize & 0x1f) + 1) * 32;
+  for reg in distributor.ICENABLER.iter().take(num_irqs.min(NUM_IRQS) / 32) {
+    reg.set(u32::MAX);
+  }
+  for intid in 0..num_irqs.min(NUM_IRQS) {
+    distributor.IPRIORITYR[intid].set(DEFAULT_PRIORITY);
+    if intid >= FIRST_SPI {
+      distributor.ITARGETSR[intid].set(1 << BOOT_CORE_ID);
+    }
+  }
+
+  distributor.CTLR.set(1);
+  GIC.init_cpu_interface();
+}
+
+/// Disable the private interrupts of the executing core and enable its CPU
+/// interface.
+///
+/// # Safety
+///
+/// - Must run on the core being set up, after `init`.
+pub unsafe fn init_secondary_core() {
+  let distributor = GIC.distributor();
+  distributor.ICENABLER[0].set(u32::MAX);
+  for intid in 0..FIRST_SPI {
+    distributor.IPRIORITYR[intid].set(DEFAULT_PRIORITY);
+  }
+
+  GIC.init_cpu_interface();
+}
+
+/// Return a reference to the IRQ manager.
+pub fn irq_manager() -> &'static impl interface::IRQManager<IRQNumberType = IRQNumber> {
+  &GIC
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+
+impl interface::IRQManager for GICv2 {
+  type IRQNumberType = IRQNumber;
+
+  fn register_handler(&self, irq: IRQNumber, descriptor: IRQDescriptor) -> Result<(), &'static str> {
+    let mut handlers = self.handlers.lock();
+
+    if handlers[irq.0].is_some() {
+      return Err("IRQ handler already registered");
+    }
+
+    handlers[irq.0] = Some(descriptor);
+    Ok(())
+  }
+
+  fn enable(&self, irq: IRQNumber) {
+    self.distributor().ISENABLER[irq.0 / 32].set(1 << (irq.0 % 32));
+  }
+
+  fn disable(&self, irq: IRQNumber) {
+    self.distributor().ICENABLER[irq.0 / 32].set(1 << (irq.0 % 32));
+  }
+
+  fn handle_pending_irqs(&self) {
+    let cpu_interface = self.cpu_interface();
+
+    loop {
+      // Reading IAR acknowledges the interrupt, EOIR completes it.
+      let iar = cpu_interface.IAR.get();
+      let intid = iar & IAR_INTID_MASK;
+      if intid >= SPURIOUS_IRQ {
+        break;
+      }
+
+      self.dispatch(IRQNumber(intid as usize));
+      cpu_interface.EOIR.set(iar);
+    }
+  }
+
+  fn print_handlers(&self) {
+    info!("Registered IRQ handlers:");
+
+    let handlers = *self.handlers.lock();
+    for (intid, descriptor) in handlers.iter().enumerate() {
+      if let Some(descriptor) = descriptor {
+        info!("      {}: {}", IRQNumber(intid), descriptor.name);
+      }
+    }
+  }
+}
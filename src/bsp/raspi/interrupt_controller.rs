@@ -0,0 +1,310 @@
+//! BCM2837 interrupt controller driver.
+//!
+//! Interrupts pass two controllers. The ARM-local controller has per-core
+//! sources like the core timers, mailboxes and the PMU. One of them is the
+//! legacy peripheral controller, which gathers the IRQs of the GPU side
+//! peripherals (system timer, UART, GPIO, ...) and a few ARM side ones like
+//! the VideoCore mailbox, and is routed to one core.
+//!
+//! # Resources
+//!
+//! - BCM2835 ARM Peripherals, chapter 7
+//! - BCM2836 ARM-local peripherals (QA7)
+
+use core::fmt;
+
+use tock_registers::interfaces::{Readable, Writeable};
+use tock_registers::register_structs;
+use tock_registers::registers::{ReadOnly, ReadWrite, WriteOnly};
+
+use super::cpu::BOOT_CORE_ID;
+use super::devicetree::{interrupt_controller_base, local_interrupt_controller_base};
+use crate::cpu::{core_id, SpinLock};
+use crate::exception::asynchronous::{interface, IRQDescriptor};
+use crate::info;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+register_structs! {
+  #[allow(non_snake_case)]
+  PeripheralRegisterBlock {
+    (0x00 => BASIC_PENDING: ReadOnly<u32>),
+    (0x04 => PENDING_1: ReadOnly<u32>),
+    (0x08 => PENDING_2: ReadOnly<u32>),
+    (0x0c => _reserved1),
+    (0x10 => ENABLE_1: WriteOnly<u32>),
+    (0x14 => ENABLE_2: WriteOnly<u32>),
+    (0x18 => ENABLE_BASIC: WriteOnly<u32>),
+    (0x1c => DISABLE_1: WriteOnly<u32>),
+    (0x20 => DISABLE_2: WriteOnly<u32>),
+    (0x24 => DISABLE_BASIC: WriteOnly<u32>),
+    (0x28 => @END),
+  }
+}
+
+register_structs! {
+  #[allow(non_snake_case)]
+  LocalRegisterBlock {
+    (0x00 => _reserved1),
+    (0x0c => GPU_INT_ROUTING: ReadWrite<u32>),
+    (0x10 => PMU_INT_ROUTING_SET: WriteOnly<u32>),
+    (0x14 => PMU_INT_ROUTING_CLR: WriteOnly<u32>),
+    (0x18 => _reserved2),
+    (0x40 => CORE_TIMER_INT_CONTROL: [ReadWrite<u32>; 4]),
+    (0x50 => CORE_MAILBOX_INT_CONTROL: [ReadWrite<u32>; 4]),
+    (0x60 => CORE_IRQ_SOURCE: [ReadOnly<u32>; 4]),
+    (0x70 => @END),
+  }
+}
+
+/// Local sources, as numbered in CORE_IRQ_SOURCE.
+const NUM_LOCAL_IRQS: usize = 12;
+const LOCAL_IRQ_TIMERS: core::ops::Range<usize> = 0..4;
+const LOCAL_IRQ_MAILBOXES: core::ops::Range<usize> = 4..8;
+const LOCAL_IRQ_GPU: usize = 8;
+const LOCAL_IRQ_PMU: usize = 9;
+
+const NUM_PERIPHERAL_IRQS: usize = 64;
+
+/// ARM side sources in the low bits of BASIC_PENDING.
+const NUM_BASIC_IRQS: usize = 8;
+const BASIC_IRQS_MASK: u32 = (1 << NUM_BASIC_IRQS) - 1;
+
+const NUM_IRQS: usize = NUM_LOCAL_IRQS + NUM_PERIPHERAL_IRQS + NUM_BASIC_IRQS;
+
+/// Handlers, local IRQs first, then peripheral and basic ones.
+type HandlerTable = [Option<IRQDescriptor>; NUM_IRQS];
+
+struct InterruptController {
+  handlers: SpinLock<HandlerTable>,
+}
+
+/// Indices of the set bits in `word`.
+fn set_bits(mut word: u32) -> impl Iterator<Item = usize> {
+  core::iter::from_fn(move || {
+    if word == 0 {
+      return None;
+    }
+
+    let bit = word.trailing_zeros() as usize;
+    word &= word - 1;
+    Some(bit)
+  })
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// An interrupt source.
+#[derive(Copy, Clone)]
+pub enum IRQNumber {
+  /// Per-core source of the local controller: 0-3 are the core timers
+  /// (CNTPS, CNTPNS, CNTHP, CNTV), 4-7 the core mailboxes and 9 the PMU.
+  Local(usize),
+
+  /// GPU side peripheral, 0-63.
+  Peripheral(usize),
+
+  /// ARM side source of the peripheral controller: 0 is the ARM timer, 1 the
+  /// VideoCore mailbox, 2-3 the doorbells.
+  Basic(usize),
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static INTERRUPT_CONTROLLER: InterruptController = InterruptController {
+  handlers: SpinLock::new("irq handlers", [None; NUM_IRQS]),
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl IRQNumber {
+  /// Slot in the handler table, if the controller supports the source.
+  fn index(&self) -> Option<usize> {
+    match *self {
+      IRQNumber::Local(irq)
+        if LOCAL_IRQ_TIMERS.contains(&irq) || LOCAL_IRQ_MAILBOXES.contains(&irq) || irq == LOCAL_IRQ_PMU =>
+      {
+        Some(irq)
+      },
+      IRQNumber::Peripheral(irq) if irq < NUM_PERIPHERAL_IRQS => Some(NUM_LOCAL_IRQS + irq),
+      IRQNumber::Basic(irq) if irq < NUM_BASIC_IRQS => Some(NUM_LOCAL_IRQS + NUM_PERIPHERAL_IRQS + irq),
+      _ => None,
+    }
+  }
+
+  fn from_index(index: usize) -> Self {
+    match index {
+      index if index < NUM_LOCAL_IRQS => IRQNumber::Local(index),
+      index if index < NUM_LOCAL_IRQS + NUM_PERIPHERAL_IRQS => IRQNumber::Peripheral(index - NUM_LOCAL_IRQS),
+      index => IRQNumber::Basic(index - NUM_LOCAL_IRQS - NUM_PERIPHERAL_IRQS),
+    }
+  }
+}
+
+impl InterruptController {
+  fn peripheral(&self) -> &PeripheralRegisterBlock {
+    unsafe { &*(interrupt_controller_base() as *const PeripheralRegisterBlock) }
+  }
+
+  fn local(&self) -> &LocalRegisterBlock {
+    unsafe { &*(local_interrupt_controller_base() as *const LocalRegisterBlock) }
+  }
+
+  /// Set or clear the enable bit of a local source for the executing core.
+  fn set_local(&self, irq: usize, enable: bool) {
+    let core = core_id();
+    let update = |reg: &ReadWrite<u32>, bit: usize| {
+      let value = reg.get();
+      reg.set(if enable {
+        value | (1 << bit)
+      } else {
+        value & !(1 << bit)
+      });
+    };
+
+    match irq {
+      irq if LOCAL_IRQ_TIMERS.contains(&irq) => update(&self.local().CORE_TIMER_INT_CONTROL[core], irq),
+      irq if LOCAL_IRQ_MAILBOXES.contains(&irq) => update(
+        &self.local().CORE_MAILBOX_INT_CONTROL[core],
+        irq - LOCAL_IRQ_MAILBOXES.start,
+      ),
+      LOCAL_IRQ_PMU if enable => self.local().PMU_INT_ROUTING_SET.set(1 << core),
+      LOCAL_IRQ_PMU => self.local().PMU_INT_ROUTING_CLR.set(1 << core),
+      _ => (),
+    }
+  }
+
+  fn dispatch(&self, irq: IRQNumber) {
+    // Copy the descriptor out, so handlers may use the controller themselves.
+    let descriptor = irq.index().and_then(|index| self.handlers.lock()[index]);
+
+    match descriptor {
+      Some(descriptor) => (descriptor.handler)(),
+      None => panic!("No handler registered for IRQ {}", irq),
+    }
+  }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl fmt::Display for IRQNumber {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      IRQNumber::Local(irq) => write!(f, "local {}", irq),
+      IRQNumber::Peripheral(irq) => write!(f, "peripheral {}", irq),
+      IRQNumber::Basic(irq) => write!(f, "basic {}", irq),
+    }
+  }
+}
+
+/// Start with all peripheral IRQs disabled and routed to the boot core.
+///
+/// # Safety
+///
+/// - Must run once, on the boot core, before IRQs are unmasked anywhere.
+pub unsafe fn init() {
+  let peripheral = INTERRUPT_CONTROLLER.peripheral();
+  peripheral.DISABLE_1.set(u32::MAX);
+  peripheral.DISABLE_2.set(u32::MAX);
+  peripheral.DISABLE_BASIC.set(BASIC_IRQS_MASK);
+
+  INTERRUPT_CONTROLLER.local().GPU_INT_ROUTING.set(BOOT_CORE_ID as u32);
+}
+
+/// Per-core setup. Nothing to do on this controller, local sources are enabled
+/// one by one.
+///
+/// # Safety
+///
+/// - Must run on the core being set up, after `init`.
+pub unsafe fn init_secondary_core() {}
+
+/// Return a reference to the IRQ manager.
+pub fn irq_manager() -> &'static impl interface::IRQManager<IRQNumberType = IRQNumber> {
+  &INTERRUPT_CONTROLLER
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+
+impl interface::IRQManager for InterruptController {
+  type IRQNumberType = IRQNumber;
+
+  fn register_handler(&self, irq: IRQNumber, descriptor: IRQDescriptor) -> Result<(), &'static str> {
+    let index = irq.index().ok_or("IRQ not supported")?;
+    let mut handlers = self.handlers.lock();
+
+    if handlers[index].is_some() {
+      return Err("IRQ handler already registered");
+    }
+
+    handlers[index] = Some(descriptor);
+    Ok(())
+  }
+
+  fn enable(&self, irq: IRQNumber) {
+    match irq {
+      IRQNumber::Local(irq) => self.set_local(irq, true),
+      IRQNumber::Peripheral(irq) if irq < 32 => self.peripheral().ENABLE_1.set(1 << irq),
+      IRQNumber::Peripheral(irq) if irq < NUM_PERIPHERAL_IRQS => self.peripheral().ENABLE_2.set(1 << (irq - 32)),
+      IRQNumber::Peripheral(_) => (),
+      IRQNumber::Basic(irq) if irq < NUM_BASIC_IRQS => self.peripheral().ENABLE_BASIC.set(1 << irq),
+      IRQNumber::Basic(_) => (),
+    }
+  }
+
+  fn disable(&self, irq: IRQNumber) {
+    match irq {
+      IRQNumber::Local(irq) => self.set_local(irq, false),
+      IRQNumber::Peripheral(irq) if irq < 32 => self.peripheral().DISABLE_1.set(1 << irq),
+      IRQNumber::Peripheral(irq) if irq < NUM_PERIPHERAL_IRQS => self.peripheral().DISABLE_2.set(1 << (irq - 32)),
+      IRQNumber::Peripheral(_) => (),
+      IRQNumber::Basic(irq) if irq < NUM_BASIC_IRQS => self.peripheral().DISABLE_BASIC.set(1 << irq),
+      IRQNumber::Basic(_) => (),
+    }
+  }
+
+  fn handle_pending_irqs(&self) {
+    let sources = self.local().CORE_IRQ_SOURCE[core_id()].get();
+
+    for source in set_bits(sources) {
+      if source != LOCAL_IRQ_GPU {
+        self.dispatch(IRQNumber::Local(source));
+        continue;
+      }
+
+      let peripheral = self.peripheral();
+      for irq in set_bits(peripheral.BASIC_PENDING.get() & BASIC_IRQS_MASK) {
+        self.dispatch(IRQNumber::Basic(irq));
+      }
+      for irq in set_bits(peripheral.PENDING_1.get()) {
+        self.dispatch(IRQNumber::Peripheral(irq));
+      }
+      for irq in set_bits(peripheral.PENDING_2.get()) {
+        self.dispatch(IRQNumber::Peripheral(32 + irq));
+      }
+    }
+  }
+
+  fn print_handlers(&self) {
+    info!("Registered IRQ handlers:");
+
+    let handlers = *self.handlers.lock();
+    for (index, descriptor) in handlers.iter().enumerate() {
+      if let Some(descriptor) = descriptor {
+        info!("      {}: {}", IRQNumber::from_index(index), descriptor.name);
+      }
+    }
+  }
+}
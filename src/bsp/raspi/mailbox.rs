@@ -1,21 +1,31 @@
+use alloc::string::String;
 use alloc::vec::Vec;
-use core::cell::RefCell;
+use core::future::Future;
+use core::mem::ManuallyDrop;
+use core::ops::RangeInclusive;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll, Waker};
 
-use bare_metal::Mutex;
-
-use crate::cpu::free;
-use crate::mem::mailbox_heap_location;
+use super::cpu::BOOT_CORE_ID;
+use super::devicetree::mailbox_base;
+use super::exception::asynchronous::irq_map::MAILBOX as MAILBOX_IRQ;
+use crate::cpu::{self, SpinLock};
+use crate::exception::asynchronous::interface::IRQManager;
+use crate::exception::asynchronous::{irq_manager, IRQDescriptor};
+use crate::mem::dma::DmaBuffer;
+use crate::task;
 
 /// Mailbox Inner Components
 pub struct MailBoxInner;
 
 /// Mailbox Framebuffer
 /// https://elinux.org/RPi_Framebuffer
-pub struct MailBox(Mutex<RefCell<MailBoxInner>>);
+pub struct MailBox(SpinLock<MailBoxInner>);
 
 impl MailBox {
   pub const fn new() -> Self {
-    Self(Mutex::new(RefCell::new(MailBoxInner {})))
+    Self(SpinLock::new("mailbox", MailBoxInner {}))
   }
 }
 
@@ -32,41 +42,149 @@ impl Into<u32> for MailboxChannel {
   }
 }
 
-const MAILBOX_BASE: u32 = 0x3F00B880;
-const MAILBOX_READ: u32 = MAILBOX_BASE + 0x0;
-const MAILBOX_STATUS: u32 = MAILBOX_BASE + 0x18;
-const MAILBOX_WRITE: u32 = MAILBOX_BASE + 0x20;
+// Register offsets from the mailbox base.
+const MAILBOX_READ: usize = 0x0;
+const MAILBOX_STATUS: usize = 0x18;
+const MAILBOX_CONFIG: usize = 0x1c;
+const MAILBOX_WRITE: usize = 0x20;
+
+/// MAILBOX_CONFIG: raise the IRQ while there is data to read.
+const MAILBOX_CONFIG_DATA_IRQ: u32 = 1 << 0;
 
 /// Global instance of Mailbox Framebuffer
-const MAILBOX: MailBox = MailBox::new();
+static MAILBOX: MailBox = MailBox::new();
+
+/// Set while a transaction owns the mailbox. Readers drop responses to other
+/// buffers, but only one of them can wait at a time, so they take turns.
+static TRANSACTION: AtomicBool = AtomicBool::new(false);
+
+/// The task waiting for a response.
+static RESPONSE_WAKER: SpinLock<Option<Waker>> = SpinLock::new("mailbox waker", None);
+
+/// Ownership of the mailbox for one request and its response. Released on
+/// drop.
+struct Transaction;
+
+impl Transaction {
+  fn try_begin() -> Option<Self> {
+    TRANSACTION
+      .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+      .ok()
+      .map(|_| Transaction)
+  }
+
+  /// Spin until the mailbox is free. A task holding it only lets go once it
+  /// is polled again, so the boot core, which runs the tasks, polls them
+  /// meanwhile.
+  fn begin() -> Self {
+    loop {
+      if let Some(transaction) = Self::try_begin() {
+        return transaction;
+      }
+
+      if cpu::core_id() as u64 == BOOT_CORE_ID {
+        task::run_ready();
+      } else {
+        core::hint::spin_loop();
+      }
+    }
+  }
+
+  /// Like `begin`, letting other tasks run while the mailbox is taken.
+  async fn begin_async() -> Self {
+    loop {
+      if let Some(transaction) = Self::try_begin() {
+        return transaction;
+      }
+      task::yield_now().await;
+    }
+  }
+}
+
+impl Drop for Transaction {
+  fn drop(&mut self) {
+    TRANSACTION.store(false, Ordering::Release);
+  }
+}
+
+/// Completes with the response to the buffer at `address`. Sleeps on the
+/// mailbox IRQ meanwhile.
+struct Response {
+  channel: MailboxChannel,
+  address: u32,
+}
+
+impl Future for Response {
+  type Output = u32;
+
+  fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<u32> {
+    let lock = MAILBOX.0.lock();
+    if let Some(message) = lock.try_read(self.channel, self.address) {
+      return Poll::Ready(message);
+    }
+
+    // Level triggered: a message that arrived since the check raises the IRQ
+    // right away.
+    *RESPONSE_WAKER.lock() = Some(cx.waker().clone());
+    lock.set_data_irq(true);
+    Poll::Pending
+  }
+}
+
+/// Data arrived. Mask the IRQ until the reader wants the next message.
+fn handle_mailbox_irq() {
+  MAILBOX.0.lock().set_data_irq(false);
+
+  if let Some(waker) = RESPONSE_WAKER.lock().take() {
+    waker.wake();
+  }
+}
 
 impl MailBoxInner {
+  fn register(&self, offset: usize) -> *mut u32 {
+    (mailbox_base() + offset) as *mut u32
+  }
+
+  /// Wait for the response to the buffer at `address`.
   // https://jsandler18.github.io/extra/mailbox.html
-  pub fn read(&self, channel: MailboxChannel) -> u32 {
+  pub fn read(&self, channel: MailboxChannel, address: u32) -> u32 {
     loop {
-      // Wait until not empty
-      loop {
-        //Read MAIL0_STATUS
-        let status = unsafe { core::ptr::read_volatile(MAILBOX_STATUS as *mut u32) };
-        if !MailStatus::from(status).empty {
-          break;
-        }
+      if let Some(message) = self.try_read(channel, address) {
+        return message;
+      }
+    }
+  }
+
+  /// Read the response to the buffer at `address`, if it is there. Messages on
+  /// other channels, and responses to other buffers, e.g. of requests given up
+  /// on, are dropped.
+  pub fn try_read(&self, channel: MailboxChannel, address: u32) -> Option<u32> {
+    loop {
+      //Read MAIL0_STATUS
+      let status = unsafe { core::ptr::read_volatile(self.register(MAILBOX_STATUS)) };
+      if MailStatus::from(status).empty {
+        return None;
       }
 
       // Read MAIL0_READ
-      let message = unsafe { core::ptr::read_volatile(MAILBOX_READ as *mut u32) };
+      let message = unsafe { core::ptr::read_volatile(self.register(MAILBOX_READ)) };
       let mail_message = MailMessage::from(message);
-      if mail_message.channel as u32 == channel.into() {
-        return message;
+      if mail_message.channel as u32 == channel.into() && message & !0xF == address {
+        return Some(message);
       }
     }
   }
 
-  pub fn send(&self, channel: MailboxChannel, data: &mut [u32]) {
+  fn set_data_irq(&self, enable: bool) {
+    let config = if enable { MAILBOX_CONFIG_DATA_IRQ } else { 0 };
+    unsafe { core::ptr::write_volatile(self.register(MAILBOX_CONFIG), config) };
+  }
+
+  pub fn send(&self, channel: MailboxChannel, buffer: &DmaBuffer) {
     // Wait until not full
     loop {
       //Read MAIL0_STATUS
-      let status = unsafe { core::ptr::read_volatile(MAILBOX_STATUS as *mut u32) };
+      let status = unsafe { core::ptr::read_volatile(self.register(MAILBOX_STATUS)) };
       if !MailStatus::from(status).full {
         break;
       }
@@ -75,19 +193,26 @@ impl MailBoxInner {
     // Send
     // Write to MAIL0_WRITE
     let channel: u32 = channel.into();
-    // Write to structure location (1 << 22)
-    unsafe { core::ptr::copy(data.as_ptr(), mailbox_heap_location() as *mut u32, data.len()) };
+    let data = buffer.bus_address() | channel;
 
-    let data = mailbox_heap_location() as u32 | channel;
-
-    unsafe { core::ptr::write_volatile(MAILBOX_WRITE as *mut u32, data) };
+    // The buffer must be written before the GPU hears of it
+    cpu::memory_barrier();
+    unsafe { core::ptr::write_volatile(self.register(MAILBOX_WRITE), data) };
   }
 }
 
-/// Copies back data from structure location (1 << 22) to variable.
-/// Assumes you know that it's the correct size.
-pub unsafe fn update_property_message(data: &mut [u32]) {
-  core::ptr::copy(mailbox_heap_location() as *mut u32, data.as_mut_ptr(), data.len());
+/// Copy a property message to DMA memory, for sending.
+fn to_dma_buffer(data: &[u32]) -> Result<DmaBuffer, &'static str> {
+  let buffer = DmaBuffer::new(data.len() * 4, 16)?;
+  unsafe { core::ptr::copy_nonoverlapping(data.as_ptr(), buffer.as_mut_ptr() as *mut u32, data.len()) };
+  Ok(buffer)
+}
+
+/// Copy the response back from DMA memory. Responses are as long as their
+/// request.
+fn from_dma_buffer(buffer: &DmaBuffer, data: &mut [u32]) {
+  cpu::memory_barrier();
+  unsafe { core::ptr::copy_nonoverlapping(buffer.as_mut_ptr() as *const u32, data.as_mut_ptr(), data.len()) };
 }
 
 pub struct MailMessage {
@@ -129,8 +254,14 @@ pub enum PropertyMessage {
   GetBitsPerPixel,
   SetBitsPerPixel(u32),
   GetBytesPerRow,
+  GetCommandLine,
+  GetArmMemory,
+  GetVcMemory,
 }
 
+/// Room for the command line in the response, in bytes.
+const COMMAND_LINE_BUFFER_SIZE: usize = 1024;
+
 impl PropertyMessage {
   pub fn to_buffer(&self) -> Vec<u32> {
     match self {
@@ -142,6 +273,13 @@ impl PropertyMessage {
       PropertyMessage::GetBitsPerPixel => [self.into(), 4, 0, 0].into(),
       PropertyMessage::GetBytesPerRow => [self.into(), 4, 0, 0].into(),
       PropertyMessage::SetBitsPerPixel(x) => [self.into(), 4, 0, *x].into(),
+      PropertyMessage::GetArmMemory => [self.into(), 8, 0, 0, 0].into(),
+      PropertyMessage::GetVcMemory => [self.into(), 8, 0, 0, 0].into(),
+      PropertyMessage::GetCommandLine => {
+        let mut buffer: Vec<u32> = [self.into(), COMMAND_LINE_BUFFER_SIZE as u32, 0].into();
+        buffer.resize(buffer.len() + COMMAND_LINE_BUFFER_SIZE / 4, 0);
+        buffer
+      },
       _ => [self.into(), 0, 0].into(),
     }
   }
@@ -163,6 +301,9 @@ impl Into<u32> for &PropertyMessage {
       PropertyMessage::GetBitsPerPixel => 0x00040005,
       PropertyMessage::SetBitsPerPixel(_) => 0x00048005,
       PropertyMessage::GetBytesPerRow => 0x00040008,
+      PropertyMessage::GetCommandLine => 0x00050001,
+      PropertyMessage::GetArmMemory => 0x00010005,
+      PropertyMessage::GetVcMemory => 0x00010006,
     }
   }
 }
@@ -209,29 +350,123 @@ pub fn build_property_message_buffer(properties: &[PropertyMessage]) -> Vec<u32>
   [prepend.to_vec(), all_tags].concat()
 }
 
-pub fn send_property_messages(properties: &[PropertyMessage]) -> Result<Vec<u32>, &str> {
+/// Register the IRQ handler, for `send_property_messages_async`.
+///
+/// # Safety
+///
+/// - Must run once, on the boot core, after the interrupt controller is set up.
+pub unsafe fn init() {
+  let descriptor = IRQDescriptor {
+    name: "Mailbox",
+    handler: handle_mailbox_irq,
+  };
+
+  MAILBOX.0.lock().set_data_irq(false);
+  if let Err(err) = irq_manager().register_handler(MAILBOX_IRQ, descriptor) {
+    panic!("Mailbox IRQ: {}", err);
+  }
+  irq_manager().enable(MAILBOX_IRQ);
+}
+
+pub fn send_property_messages(properties: &[PropertyMessage]) -> Result<Vec<u32>, &'static str> {
   let mut buffer = build_property_message_buffer(properties);
   let (_, buffer, _) = unsafe { buffer.align_to_mut::<u32>() };
-  free(|cs| {
-    if let Ok(lock) = MAILBOX.0.borrow(*cs).try_borrow_mut() {
-      lock.send(MailboxChannel::Property, buffer);
-      // Wait for Response
-      lock.read(MailboxChannel::Property);
-      unsafe {
-        update_property_message(buffer);
-      }
-    } else {
-      panic!("Failed to send property to mailbox");
+  send_property_buffer(buffer)?;
+
+  Ok(Vec::from(buffer))
+}
+
+/// Send a ready-made property buffer and replace it with the response. Doesn't
+/// touch the heap.
+fn send_property_buffer(buffer: &mut [u32]) -> Result<(), &'static str> {
+  let dma_buffer = to_dma_buffer(buffer)?;
+  {
+    let _transaction = Transaction::begin();
+    let lock = MAILBOX.0.lock();
+    lock.send(MailboxChannel::Property, &dma_buffer);
+    // Wait for Response
+    lock.read(MailboxChannel::Property, dma_buffer.bus_address());
+  }
+  from_dma_buffer(&dma_buffer, buffer);
+
+  check_property_response(buffer)
+}
+
+/// Like `send_property_messages`, letting other tasks run while the firmware
+/// works on the request.
+#[allow(dead_code)]
+pub async fn send_property_messages_async(properties: &[PropertyMessage]) -> Result<Vec<u32>, &'static str> {
+  let mut buffer = build_property_message_buffer(properties);
+  let dma_buffer = to_dma_buffer(&buffer)?;
+  let dma_buffer = {
+    let _transaction = Transaction::begin_async().await;
+
+    // If this is dropped before the response, the firmware may still write the
+    // buffer. Leak it then.
+    let dma_buffer = ManuallyDrop::new(dma_buffer);
+    MAILBOX.0.lock().send(MailboxChannel::Property, &dma_buffer);
+    Response {
+      channel: MailboxChannel::Property,
+      address: dma_buffer.bus_address(),
     }
-  });
+    .await;
+    ManuallyDrop::into_inner(dma_buffer)
+  };
+  from_dma_buffer(&dma_buffer, &mut buffer);
+
+  check_property_response(&buffer)?;
+
+  Ok(buffer)
+}
 
+fn check_property_response(buffer: &[u32]) -> Result<(), &'static str> {
   if let Some(response) = BufferRequestResultCode::from_buffer_data(buffer) {
     match response {
       BufferRequestResultCode::Request => Err("Got Request Result back! Never processed?"),
-      BufferRequestResultCode::ResponseSuccess => Ok(Vec::from(buffer)),
+      BufferRequestResultCode::ResponseSuccess => Ok(()),
       BufferRequestResultCode::ResponseError => Err("Mailbox returned error!"),
     }
   } else {
     panic!("Unknown Response from Mailbox! {:#?}", buffer[1]);
   }
 }
+
+/// The kernel command line, as passed by the firmware.
+pub fn command_line() -> Result<String, &'static str> {
+  let response = send_property_messages(&[PropertyMessage::GetCommandLine])?;
+
+  // Header, then tag ID, buffer size and response code, which holds the length
+  // of the value.
+  let length = (response[4] & !(1 << 31)) as usize;
+  let bytes: Vec<u8> = response[5..]
+    .iter()
+    .flat_map(|word| word.to_le_bytes())
+    .take(length.min(COMMAND_LINE_BUFFER_SIZE))
+    .collect();
+
+  let cmdline = core::str::from_utf8(&bytes).map_err(|_| "Command line is not UTF-8")?;
+  Ok(String::from(cmdline.trim_end_matches('\0')))
+}
+
+/// The physical memory of the ARM cores and of the VideoCore, as split by the
+/// firmware (`gpu_mem` in config.txt).
+///
+/// Asks the firmware without using the heap, so it can size the heap.
+pub fn memory_split() -> Result<(RangeInclusive<usize>, RangeInclusive<usize>), &'static str> {
+  let arm_memory: u32 = (&PropertyMessage::GetArmMemory).into();
+  let vc_memory: u32 = (&PropertyMessage::GetVcMemory).into();
+
+  // Header, both tags with base and size, end tag and padding to 16 bytes.
+  let mut buffer: [u32; 16] = [0; 16];
+  buffer[0] = (buffer.len() * 4) as u32;
+  buffer[1] = BufferRequestResultCode::Request.into();
+  buffer[2..7].copy_from_slice(&[arm_memory, 8, 0, 0, 0]);
+  buffer[7..12].copy_from_slice(&[vc_memory, 8, 0, 0, 0]);
+  send_property_buffer(&mut buffer)?;
+
+  let region = |base: u32, size: u32| match size {
+    0 => Err("Firmware reported an empty memory region"),
+    size => Ok(base as usize..=base as usize + size as usize - 1),
+  };
+  Ok((region(buffer[5], buffer[6])?, region(buffer[10], buffer[11])?))
+}
@@ -0,0 +1,192 @@
+//! BSP Memory Management.
+
+use core::cell::UnsafeCell;
+use core::ops::RangeInclusive;
+
+use crate::mem::dma::dma_range_inclusive;
+use crate::mem::mmu::interface::Mmu;
+use crate::mem::mmu::{
+  mmu, AccessPermissions, AttributeFields, KernelVirtualLayout, MemAttributes, StackGuard, TranslationDescriptor,
+};
+
+// Symbols from the linker script.
+extern "Rust" {
+  static __code_start: UnsafeCell<()>;
+  static __code_end_exclusive: UnsafeCell<()>;
+  static __boot_core_stack_guard_start: UnsafeCell<()>;
+  static __boot_core_stack_guard_end_exclusive: UnsafeCell<()>;
+  static __secondary_core_stacks_start: UnsafeCell<()>;
+  static __secondary_core_stack_slot_size: UnsafeCell<()>;
+  static __stack_guard_size: UnsafeCell<()>;
+}
+
+/// The board's physical memory map.
+#[rustfmt::skip]
+pub mod map {
+  /// Size of one page, matching `PAGE_SIZE` in link.ld and the MMU granule.
+  pub const PAGE_SIZE: usize = 64 * 1024;
+
+  /// Where devices see ARM memory, through the VideoCore's uncached alias.
+  pub const DMA_BUS_ALIAS: u32 = 0xC000_0000;
+
+  /// Offsets of the peripherals from the start of the MMIO range. The same on
+  /// all boards.
+  #[cfg(feature = "bsp_rpi3")]
+  pub const INTERRUPT_CONTROLLER_OFFSET: usize = 0x0000_B200;
+  pub const SYSTEM_TIMER_OFFSET: usize = 0x0000_3000;
+  pub const MAILBOX_OFFSET:    usize = 0x0000_B880;
+  pub const PM_OFFSET:         usize = 0x0010_0000;
+  pub const PL011_UART_OFFSET: usize = 0x0020_1000;
+
+  /// Physical devices. The device tree has the final say, these are the
+  /// defaults for when there is none.
+  #[cfg(feature = "bsp_rpi3")]
+  pub mod mmio {
+    use super::*;
+
+    pub const START:            usize = 0x3F00_0000;
+    pub const INTERRUPT_CONTROLLER_START: usize = START + INTERRUPT_CONTROLLER_OFFSET;
+    pub const MAILBOX_START:    usize = START + MAILBOX_OFFSET;
+    pub const PM_START:         usize = START + PM_OFFSET;
+    pub const PL011_UART_START: usize = START + PL011_UART_OFFSET;
+    pub const SYSTEM_TIMER_START: usize = START + SYSTEM_TIMER_OFFSET;
+    pub const LOCAL_INTERRUPT_CONTROLLER_START: usize = 0x4000_0000;
+    pub const END_INCLUSIVE:    usize = 0x4000_FFFF;
+  }
+
+  /// Physical devices. The device tree has the final say, these are the
+  /// defaults for when there is none.
+  #[cfg(feature = "bsp_rpi4")]
+  pub mod mmio {
+    use super::*;
+
+    pub const START:            usize = 0xFE00_0000;
+    pub const MAILBOX_START:    usize = START + MAILBOX_OFFSET;
+    pub const PM_START:         usize = START + PM_OFFSET;
+    pub const PL011_UART_START: usize = START + PL011_UART_OFFSET;
+    pub const SYSTEM_TIMER_START: usize = START + SYSTEM_TIMER_OFFSET;
+    pub const GICD_START:       usize = 0xFF84_1000;
+    pub const GICC_START:       usize = 0xFF84_2000;
+    pub const END_INCLUSIVE:    usize = 0xFF84_FFFF;
+  }
+
+  /// The last address the kernel maps. Peripherals come last on both boards.
+  pub const END_INCLUSIVE: usize = mmio::END_INCLUSIVE;
+}
+
+/// The kernel's address space layout.
+pub mod mmu {
+  use super::*;
+
+  const NUM_MEM_RANGES: usize = 3;
+
+  /// One guard page below the stack of each core.
+  static STACK_GUARDS: [StackGuard; 4] = [
+    StackGuard {
+      name: "Boot core stack guard",
+      range: boot_core_stack_guard_range_inclusive,
+    },
+    StackGuard {
+      name: "Core 1 stack guard",
+      range: || secondary_core_stack_guard_range_inclusive(1),
+    },
+    StackGuard {
+      name: "Core 2 stack guard",
+      range: || secondary_core_stack_guard_range_inclusive(2),
+    },
+    StackGuard {
+      name: "Core 3 stack guard",
+      range: || secondary_core_stack_guard_range_inclusive(3),
+    },
+  ];
+
+  /// The kernel's memory layout. Normal DRAM that isn't listed is mapped
+  /// cacheable, read-write and non-executable.
+  pub static LAYOUT: KernelVirtualLayout<NUM_MEM_RANGES> = KernelVirtualLayout::new(
+    map::END_INCLUSIVE,
+    AttributeFields {
+      mem_attributes: MemAttributes::CacheableDRAM,
+      acc_perms: AccessPermissions::ReadWrite,
+      execute_never: true,
+    },
+    [
+      TranslationDescriptor {
+        name: "Kernel code and RO data",
+        range: code_range_inclusive,
+        attribute_fields: AttributeFields {
+          mem_attributes: MemAttributes::CacheableDRAM,
+          acc_perms: AccessPermissions::ReadOnly,
+          execute_never: false,
+        },
+      },
+      TranslationDescriptor {
+        name: "DMA region",
+        range: dma_range_inclusive,
+        attribute_fields: AttributeFields {
+          mem_attributes: MemAttributes::WriteCombining,
+          acc_perms: AccessPermissions::ReadWrite,
+          execute_never: true,
+        },
+      },
+      TranslationDescriptor {
+        name: "Device MMIO",
+        range: || map::mmio::START..=map::mmio::END_INCLUSIVE,
+        attribute_fields: AttributeFields {
+          mem_attributes: MemAttributes::Device,
+          acc_perms: AccessPermissions::ReadWrite,
+          execute_never: true,
+        },
+      },
+    ],
+    &STACK_GUARDS,
+  );
+
+  /// Return a reference to the virtual memory layout.
+  pub fn virt_mem_layout() -> &'static KernelVirtualLayout<NUM_MEM_RANGES> {
+    &LAYOUT
+  }
+
+  fn code_range_inclusive() -> RangeInclusive<usize> {
+    // Notice the subtraction to turn the exclusive end into an inclusive end.
+    #[allow(clippy::range_minus_one)]
+    unsafe {
+      RangeInclusive::new(__code_start.get() as usize, __code_end_exclusive.get() as usize - 1)
+    }
+  }
+
+  fn boot_core_stack_guard_range_inclusive() -> RangeInclusive<usize> {
+    #[allow(clippy::range_minus_one)]
+    unsafe {
+      RangeInclusive::new(
+        __boot_core_stack_guard_start.get() as usize,
+        __boot_core_stack_guard_end_exclusive.get() as usize - 1,
+      )
+    }
+  }
+
+  /// Core n's stack slot starts with its guard, see link.ld.
+  fn secondary_core_stack_guard_range_inclusive(core_id: usize) -> RangeInclusive<usize> {
+    let (slots_start, slot_size, guard_size) = unsafe {
+      (
+        __secondary_core_stacks_start.get() as usize,
+        __secondary_core_stack_slot_size.get() as usize,
+        __stack_guard_size.get() as usize,
+      )
+    };
+
+    let start = slots_start + (core_id - 1) * slot_size;
+    start..=start + guard_size - 1
+  }
+}
+
+/// Map the scan-out framebuffer write-combining. The GPU reads it behind the
+/// CPU's back, so it must not be cached, but writes can still be gathered.
+pub fn map_framebuffer(start: usize, size: usize) -> Result<(), &'static str> {
+  let attributes = AttributeFields {
+    mem_attributes: MemAttributes::WriteCombining,
+    acc_perms: AccessPermissions::ReadWrite,
+    execute_never: true,
+  };
+
+  unsafe { mmu().remap(start..=start + size - 1, attributes) }
+}
@@ -0,0 +1,77 @@
+//! Power management.
+//!
+//! Reboot and power-off through the watchdog of the BCM power management
+//! block. When the watchdog expires, the SoC resets. Before that, the reset
+//! status register can tell the firmware which partition to boot from, and
+//! partition 63 makes it halt instead of booting.
+//!
+//! # Resources
+//!
+//! - Linux, drivers/watchdog/bcm2835_wdt.c
+
+use super::devicetree::pm_base;
+use crate::cpu;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+// Register offsets from the power management base.
+const PM_RSTC: usize = 0x1c;
+const PM_RSTS: usize = 0x20;
+const PM_WDOG: usize = 0x24;
+
+/// Every write must carry the password, or it is ignored.
+const PM_PASSWORD: u32 = 0x5a00_0000;
+
+/// Timeout field of PM_WDOG. The watchdog counts 65536 ticks per second.
+const PM_WDOG_TIME_SET: u32 = 0x000f_ffff;
+
+const PM_RSTC_WRCFG_CLR: u32 = 0xffff_ffcf;
+const PM_RSTC_WRCFG_FULL_RESET: u32 = 0x0000_0020;
+
+/// Partition 63 in the scattered partition bits of RSTS. Tells the firmware to
+/// halt.
+const PM_RSTS_PARTITION_HALT: u32 = 0x0000_0555;
+
+/// Ticks until the reset, short enough to not be noticeable.
+const RESET_TICKS: u32 = 10;
+
+fn register(offset: usize) -> *mut u32 {
+  (pm_base() + offset) as *mut u32
+}
+
+/// Let the watchdog reset the SoC after `ticks`.
+fn start_watchdog(ticks: u32) {
+  unsafe {
+    core::ptr::write_volatile(register(PM_WDOG), PM_PASSWORD | (ticks & PM_WDOG_TIME_SET));
+
+    let rstc = core::ptr::read_volatile(register(PM_RSTC));
+    core::ptr::write_volatile(
+      register(PM_RSTC),
+      PM_PASSWORD | (rstc & PM_RSTC_WRCFG_CLR) | PM_RSTC_WRCFG_FULL_RESET,
+    );
+  }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Reset the board.
+pub fn reboot() -> ! {
+  start_watchdog(RESET_TICKS);
+  cpu::wait_forever()
+}
+
+/// Power off, as far as the board allows. The firmware halts on the next boot
+/// instead of loading the kernel.
+#[allow(dead_code)]
+pub fn halt() -> ! {
+  unsafe {
+    let rsts = core::ptr::read_volatile(register(PM_RSTS));
+    core::ptr::write_volatile(register(PM_RSTS), PM_PASSWORD | rsts | PM_RSTS_PARTITION_HALT);
+  }
+
+  reboot()
+}
@@ -0,0 +1,216 @@
+//! BCM system timer driver.
+//!
+//! A free-running 64 bit counter at 1 MHz, independent of the ARM generic
+//! timer, with four 32 bit compare channels. Each channel raises its IRQ when
+//! the low half of the counter matches it. The GPU firmware owns channels 0
+//! and 2. Channel 1 serves alarms, channel 3 `sleep_until`.
+//!
+//! # Resources
+//!
+//! - BCM2835 ARM Peripherals, chapter 12
+
+use core::time::Duration;
+
+use tock_registers::interfaces::{Readable, Writeable};
+use tock_registers::register_structs;
+use tock_registers::registers::{ReadOnly, ReadWrite};
+
+use super::cpu::BOOT_CORE_ID;
+use super::devicetree::system_timer_base;
+use super::exception::asynchronous::irq_map::{SYSTEM_TIMER_C1, SYSTEM_TIMER_C3};
+use crate::cpu::{self, SpinLock};
+use crate::exception::asynchronous::interface::IRQManager;
+use crate::exception::asynchronous::{irq_manager, IRQDescriptor};
+use crate::time::{self, Instant};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+register_structs! {
+  #[allow(non_snake_case)]
+  RegisterBlock {
+    (0x00 => CS: ReadWrite<u32>),
+    (0x04 => CLO: ReadOnly<u32>),
+    (0x08 => CHI: ReadOnly<u32>),
+    (0x0c => C: [ReadWrite<u32>; 4]),
+    (0x1c => @END),
+  }
+}
+
+const TICKS_PER_S: u64 = 1_000_000;
+
+const ALARM_CHANNEL: usize = 1;
+const SLEEP_CHANNEL: usize = 3;
+
+/// Compare values are 32 bits wide. Stay well below the wrap-around, so a
+/// target can't be mistaken for one that already passed.
+const MAX_COMPARE_TICKS: u64 = 1 << 31;
+
+struct SystemTimer {
+  alarm: SpinLock<Option<fn()>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static SYSTEM_TIMER: SystemTimer = SystemTimer {
+  alarm: SpinLock::new("system timer alarm", None),
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl SystemTimer {
+  fn registers(&self) -> &RegisterBlock {
+    unsafe { &*(system_timer_base() as *const RegisterBlock) }
+  }
+
+  fn read_counter(&self) -> u64 {
+    let registers = self.registers();
+
+    // The halves are read one at a time. Retry if the low one wrapped.
+    loop {
+      let high = registers.CHI.get();
+      let low = registers.CLO.get();
+      if registers.CHI.get() == high {
+        return (high as u64) << 32 | low as u64;
+      }
+    }
+  }
+
+  /// Acknowledge a match on `channel`.
+  fn clear_match(&self, channel: usize) {
+    self.registers().CS.set(1 << channel);
+  }
+
+  /// Let `channel` match when the counter reaches `target`.
+  fn arm(&self, channel: usize, target: u64) {
+    self.clear_match(channel);
+    self.registers().C[channel].set(target as u32);
+  }
+}
+
+fn duration_to_ticks(duration: Duration) -> u64 {
+  duration.as_micros().min(u64::MAX as u128) as u64
+}
+
+fn handle_alarm_irq() {
+  SYSTEM_TIMER.clear_match(ALARM_CHANNEL);
+
+  // One-shot. Take the callback out, so it can set the next alarm.
+  let callback = SYSTEM_TIMER.alarm.lock().take();
+  if let Some(callback) = callback {
+    callback();
+  }
+}
+
+/// Waking up is all the sleeping core needs.
+fn handle_sleep_irq() {
+  SYSTEM_TIMER.clear_match(SLEEP_CHANNEL);
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Register and enable the compare channel IRQs. They are routed to the boot
+/// core.
+///
+/// # Safety
+///
+/// - Must run once, on the boot core, after the interrupt controller is set up.
+pub unsafe fn init() {
+  let handlers = [
+    (SYSTEM_TIMER_C1, "System timer alarm", handle_alarm_irq as fn()),
+    (SYSTEM_TIMER_C3, "System timer sleep", handle_sleep_irq),
+  ];
+
+  for (irq, name, handler) in handlers {
+    if let Err(err) = irq_manager().register_handler(irq, IRQDescriptor { name, handler }) {
+      panic!("System timer IRQ: {}", err);
+    }
+    irq_manager().enable(irq);
+  }
+}
+
+/// Return a reference to the system timer.
+#[allow(dead_code)]
+pub fn system_timer() -> &'static impl time::interface::TimeManager {
+  &SYSTEM_TIMER
+}
+
+/// Call `callback` from the alarm interrupt once `delay` has passed. Replaces
+/// a pending alarm.
+#[allow(dead_code)]
+pub fn set_alarm(delay: Duration, callback: fn()) -> Result<(), &'static str> {
+  let ticks = duration_to_ticks(delay);
+  if ticks >= MAX_COMPARE_TICKS {
+    return Err("Alarm delay too long");
+  }
+
+  // Hold the lock while arming, so the IRQ can't see the old callback.
+  let mut alarm = SYSTEM_TIMER.alarm.lock();
+  *alarm = Some(callback);
+  SYSTEM_TIMER.arm(ALARM_CHANNEL, SYSTEM_TIMER.read_counter() + ticks.max(1));
+  Ok(())
+}
+
+/// Drop the pending alarm. Returns whether there was one.
+#[allow(dead_code)]
+pub fn cancel_alarm() -> bool {
+  SYSTEM_TIMER.alarm.lock().take().is_some()
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+
+impl time::interface::TimeManager for SystemTimer {
+  fn resolution(&self) -> Duration {
+    Duration::from_nanos(1_000_000_000 / TICKS_PER_S)
+  }
+
+  fn uptime(&self) -> Duration {
+    Duration::from_micros(self.read_counter())
+  }
+
+  fn spin_for(&self, duration: Duration) {
+    let deadline = self.read_counter().saturating_add(duration_to_ticks(duration));
+
+    while self.read_counter() < deadline {}
+  }
+
+  /// Only the boot core receives the compare IRQs, the other cores spin.
+  fn sleep_until(&self, deadline: Instant) {
+    let deadline = duration_to_ticks(deadline.since_power_on());
+
+    if cpu::core_id() as u64 != BOOT_CORE_ID {
+      while self.read_counter() < deadline {}
+      return;
+    }
+
+    loop {
+      // Same as for the generic timer: check and sleep with IRQs masked.
+      let saved = cpu::local_irq_mask_save();
+      let now = self.read_counter();
+      if now >= deadline {
+        cpu::local_irq_restore(saved);
+        break;
+      }
+
+      // Far deadlines take several rounds.
+      let target = deadline.min(now + MAX_COMPARE_TICKS);
+      self.arm(SLEEP_CHANNEL, target);
+
+      // The target may have passed while arming, it would only match again
+      // after the wrap-around.
+      if self.read_counter() < target {
+        cpu::wait_for_interrupt();
+      }
+      cpu::local_irq_restore(saved);
+    }
+  }
+}
@@ -0,0 +1,99 @@
+//! Kernel command line.
+//!
+//! The firmware passes the contents of `cmdline.txt` (or QEMU's `-append`)
+//! on request through the mailbox. Options are whitespace separated `key=value`
+//! pairs or bare `key` flags. If a key appears more than once, the last one
+//! wins. Everything the kernel doesn't know is ignored, the firmware adds
+//! plenty of options meant for Linux.
+
+use alloc::boxed::Box;
+use core::str::FromStr;
+
+use crate::bsp::mailbox::command_line;
+use crate::cpu::SpinLock;
+use crate::{info, warn};
+
+/// The parsed command line. Empty until `init` ran.
+static BOOT_OPTIONS: SpinLock<BootOptions> = SpinLock::new("boot options", BootOptions::new(""));
+
+/// Typed access to the options on the kernel command line.
+#[derive(Copy, Clone)]
+pub struct BootOptions {
+  cmdline: &'static str,
+}
+
+impl BootOptions {
+  pub const fn new(cmdline: &'static str) -> Self {
+    Self { cmdline }
+  }
+
+  /// The raw value of `key`. Flags without a value yield `""`.
+  pub fn get(&self, key: &str) -> Option<&'static str> {
+    self
+      .cmdline
+      .split_whitespace()
+      .filter_map(|option| match option.split_once('=') {
+        Some((k, value)) => (k == key).then(|| value),
+        None => (option == key).then(|| ""),
+      })
+      .last()
+  }
+
+  /// The value of `key` parsed as `T`. Unparsable values are reported and
+  /// treated as missing.
+  pub fn get_parsed<T: FromStr>(&self, key: &str) -> Option<T> {
+    let value = self.get(key)?;
+    let parsed = value.parse().ok();
+    if parsed.is_none() {
+      warn!("Ignoring invalid boot option {}={}", key, value);
+    }
+
+    parsed
+  }
+
+  pub fn get_u32(&self, key: &str) -> Option<u32> {
+    self.get_parsed(key)
+  }
+
+  /// A flag. A bare `key` counts as set.
+  #[allow(dead_code)]
+  pub fn get_bool(&self, key: &str) -> Option<bool> {
+    match self.get(key)? {
+      "" | "1" | "true" | "on" | "yes" => Some(true),
+      "0" | "false" | "off" | "no" => Some(false),
+      value => {
+        warn!("Ignoring invalid boot option {}={}", key, value);
+        None
+      },
+    }
+  }
+
+  /// A `<width>x<height>` pair.
+  pub fn get_dimensions(&self, key: &str) -> Option<(u32, u32)> {
+    let value = self.get(key)?;
+    let parsed = value
+      .split_once('x')
+      .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)));
+    if parsed.is_none() {
+      warn!("Ignoring invalid boot option {}={}", key, value);
+    }
+
+    parsed
+  }
+}
+
+/// Fetch the command line from the firmware. Needs the heap.
+pub fn init() {
+  match command_line() {
+    Ok(cmdline) => {
+      info!("Command line: {}", cmdline);
+      *BOOT_OPTIONS.lock() = BootOptions::new(Box::leak(cmdline.into_boxed_str()));
+    },
+    Err(err) => warn!("Failed to read the command line: {}", err),
+  }
+}
+
+/// The kernel's boot options.
+pub fn boot_options() -> BootOptions {
+  *BOOT_OPTIONS.lock()
+}
@@ -3,6 +3,9 @@
 mod arch_cpu;
 
 mod boot;
+mod lock;
+pub mod smp;
 
 #[cfg(target_arch = "aarch64")]
 pub use arch_cpu::*;
+pub use lock::{SpinLock, SpinLockGuard};
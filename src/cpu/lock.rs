@@ -0,0 +1,108 @@
+//! SMP-safe locking.
+//!
+//! A ticket lock that also masks interrupts on the executing core for as long
+//! as it is held, so the same lock can be shared between cores and between
+//! interrupt handlers and regular code.
+
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use crate::cpu::{core_id, local_irq_mask_save, local_irq_restore};
+
+/// Marks an unowned lock. Owners are stored as core ID + 1.
+const NO_OWNER: usize = 0;
+
+/// Ticket spin lock with interrupt masking.
+pub struct SpinLock<T> {
+  /// Used in diagnostics.
+  name: &'static str,
+  next_ticket: AtomicU32,
+  now_serving: AtomicU32,
+  owner: AtomicUsize,
+  data: UnsafeCell<T>,
+}
+
+/// Grants access to the locked data. The lock is released and the interrupt
+/// state restored on drop.
+pub struct SpinLockGuard<'a, T> {
+  lock: &'a SpinLock<T>,
+  irq_state: u64,
+}
+
+unsafe impl<T: Send> Sync for SpinLock<T> {}
+unsafe impl<T: Send> Send for SpinLock<T> {}
+
+impl<T> SpinLock<T> {
+  pub const fn new(name: &'static str, data: T) -> Self {
+    Self {
+      name,
+      next_ticket: AtomicU32::new(0),
+      now_serving: AtomicU32::new(0),
+      owner: AtomicUsize::new(NO_OWNER),
+      data: UnsafeCell::new(data),
+    }
+  }
+
+  /// Acquire the lock, spinning until it is our turn.
+  ///
+  /// Panics if the executing core already holds the lock, as waiting would
+  /// never finish.
+  pub fn lock(&self) -> SpinLockGuard<T> {
+    let irq_state = local_irq_mask_save();
+
+    if self.owner.load(Ordering::Relaxed) == core_id() + 1 {
+      local_irq_restore(irq_state);
+      panic!("Deadlock: lock `{}` is already held by core {}", self.name, core_id());
+    }
+
+    let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+    while self.now_serving.load(Ordering::Acquire) != ticket {
+      core::hint::spin_loop();
+    }
+
+    self.owner.store(core_id() + 1, Ordering::Relaxed);
+    SpinLockGuard { lock: self, irq_state }
+  }
+
+  /// Acquire the lock only if nobody holds it.
+  #[allow(dead_code)]
+  pub fn try_lock(&self) -> Option<SpinLockGuard<T>> {
+    let irq_state = local_irq_mask_save();
+
+    let ticket = self.now_serving.load(Ordering::Relaxed);
+    if self
+      .next_ticket
+      .compare_exchange(ticket, ticket.wrapping_add(1), Ordering::Acquire, Ordering::Relaxed)
+      .is_err()
+    {
+      local_irq_restore(irq_state);
+      return None;
+    }
+
+    self.owner.store(core_id() + 1, Ordering::Relaxed);
+    Some(SpinLockGuard { lock: self, irq_state })
+  }
+}
+
+impl<T> Deref for SpinLockGuard<'_, T> {
+  type Target = T;
+
+  fn deref(&self) -> &T {
+    unsafe { &*self.lock.data.get() }
+  }
+}
+
+impl<T> DerefMut for SpinLockGuard<'_, T> {
+  fn deref_mut(&mut self) -> &mut T {
+    unsafe { &mut *self.lock.data.get() }
+  }
+}
+
+impl<T> Drop for SpinLockGuard<'_, T> {
+  fn drop(&mut self) {
+    self.lock.owner.store(NO_OWNER, Ordering::Relaxed);
+    self.lock.now_serving.fetch_add(1, Ordering::Release);
+    local_irq_restore(self.irq_state);
+  }
+}
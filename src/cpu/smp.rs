@@ -0,0 +1,111 @@
+//! Symmetric multiprocessing.
+//!
+//! Cores 1-3 are released from the firmware spin table by the boot core, set
+//! themselves up in `kernel_secondary_main` and then sleep until a job is
+//! handed to them with `run_on_core`.
+
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use core::time::Duration;
+
+use crate::bsp::cpu::{release_secondary_core, BOOT_CORE_ID, NUM_CORES};
+use crate::cpu::{core_id, send_event, wait_for_event};
+use crate::time::Instant;
+use crate::{info, warn};
+
+/// How long a released core gets to report in.
+const STARTUP_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Marks an empty job slot.
+const NO_JOB: usize = 0;
+
+/// Per core state, shared between the core and everyone handing it work.
+struct CoreSlot {
+  online: AtomicBool,
+  /// The queued `fn()`, or `NO_JOB`.
+  job: AtomicUsize,
+}
+
+impl CoreSlot {
+  const fn new() -> Self {
+    Self {
+      online: AtomicBool::new(false),
+      job: AtomicUsize::new(NO_JOB),
+    }
+  }
+}
+
+static CORES: [CoreSlot; NUM_CORES] = [CoreSlot::new(), CoreSlot::new(), CoreSlot::new(), CoreSlot::new()];
+
+/// Release all secondary cores and wait for each to come online.
+pub fn start_secondary_cores() {
+  // Provided by boot.s.
+  extern "Rust" {
+    static _start_secondary: UnsafeCell<()>;
+  }
+
+  let entry = unsafe { _start_secondary.get() as usize };
+
+  for core in (0..NUM_CORES).filter(|core| *core as u64 != BOOT_CORE_ID) {
+    unsafe { release_secondary_core(core, entry) };
+
+    let released = Instant::now();
+    while !is_online(core) && released.elapsed() < STARTUP_TIMEOUT {}
+
+    if is_online(core) {
+      info!("Core {} online", core);
+    } else {
+      warn!("Core {} did not come online", core);
+    }
+  }
+}
+
+/// Whether a core has come up and accepts jobs.
+pub fn is_online(core_id: usize) -> bool {
+  CORES
+    .get(core_id)
+    .map_or(false, |slot| slot.online.load(Ordering::Acquire))
+}
+
+/// Queue `job` on a secondary core. Each core holds at most one pending job.
+#[allow(dead_code)]
+pub fn run_on_core(core_id: usize, job: fn()) -> Result<(), &'static str> {
+  let slot = CORES.get(core_id).ok_or("No such core")?;
+
+  if core_id == self::core_id() {
+    return Err("Cannot queue a job on the executing core");
+  }
+
+  if !slot.online.load(Ordering::Acquire) {
+    return Err("Core is not online");
+  }
+
+  slot
+    .job
+    .compare_exchange(NO_JOB, job as usize, Ordering::AcqRel, Ordering::Acquire)
+    .map_err(|_| "Core is busy")?;
+
+  send_event();
+  Ok(())
+}
+
+/// Work loop of the secondary cores. Runs queued jobs, sleeps otherwise.
+pub fn secondary_core_loop(core_id: usize) -> ! {
+  let slot = &CORES[core_id];
+  slot.online.store(true, Ordering::Release);
+  send_event();
+
+  loop {
+    let job = slot.job.load(Ordering::Acquire);
+    if job == NO_JOB {
+      wait_for_event();
+      continue;
+    }
+
+    // Only ever stored from a `fn()` in `run_on_core`.
+    let job: fn() = unsafe { core::mem::transmute(job) };
+    job();
+
+    slot.job.store(NO_JOB, Ordering::Release);
+  }
+}
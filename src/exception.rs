@@ -0,0 +1,26 @@
+//! Synchronous and asynchronous exception handling.
+
+#[cfg(target_arch = "aarch64")]
+#[path = "arch/aarch64/exception.rs"]
+mod arch_exception;
+
+pub mod asynchronous;
+
+//--------------------------------------------------------------------------------------------------
+// Architectural Public Reexports
+//--------------------------------------------------------------------------------------------------
+pub use arch_exception::{current_privilege_level, handling_init};
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Kernel privilege levels.
+#[allow(dead_code)]
+#[derive(Eq, PartialEq)]
+pub enum PrivilegeLevel {
+  User,
+  Kernel,
+  Hypervisor,
+  Unknown,
+}
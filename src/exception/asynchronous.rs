@@ -0,0 +1,54 @@
+//! Asynchronous exception handling, aka interrupts.
+//!
+//! The BSP provides the interrupt controller driver. Drivers register a
+//! handler for their IRQ and enable it, the IRQ vector then asks the
+//! controller for the pending IRQs and dispatches them.
+
+use core::fmt;
+
+//--------------------------------------------------------------------------------------------------
+// BSP Public Reexports
+//--------------------------------------------------------------------------------------------------
+pub use crate::bsp::exception::asynchronous::{irq_manager, IRQNumber};
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A registered interrupt handler. Runs with IRQs masked, on the exception
+/// stack.
+#[derive(Copy, Clone)]
+pub struct IRQDescriptor {
+  /// Descriptive name, for diagnostics.
+  pub name: &'static str,
+
+  pub handler: fn(),
+}
+
+/// Interrupt management interfaces.
+pub mod interface {
+  use super::*;
+
+  /// IRQ management functions.
+  pub trait IRQManager {
+    /// The IRQ number type the controller uses.
+    type IRQNumberType: Copy + fmt::Display;
+
+    /// Register a handler. Each IRQ takes exactly one.
+    fn register_handler(&self, irq: Self::IRQNumberType, descriptor: IRQDescriptor) -> Result<(), &'static str>;
+
+    /// Let the IRQ through to the executing core, or to the core the
+    /// controller routes it to.
+    fn enable(&self, irq: Self::IRQNumberType);
+
+    /// Stop the IRQ from being signalled.
+    fn disable(&self, irq: Self::IRQNumberType);
+
+    /// Dispatch all pending IRQs of the executing core to their handlers.
+    /// Called from the IRQ vector.
+    fn handle_pending_irqs(&self);
+
+    /// Print the registered handlers.
+    fn print_handlers(&self);
+  }
+}
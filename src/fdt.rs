@@ -0,0 +1,695 @@
+//! Flattened device tree.
+//!
+//! A read-only view of a device tree blob (DTB), as handed over by the
+//! firmware. Nothing is allocated or copied: nodes and properties borrow from
+//! the blob. The parser only depends on `core`, so it works on the host with
+//! DTB files dumped from a board just as well.
+//!
+//! # Resources
+//!
+//! - https://github.com/devicetree-org/devicetree-specification/releases
+
+use core::str;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+
+/// Size of the header in the oldest supported version.
+const HEADER_SIZE: usize = 40;
+
+/// Oldest version with the layout used here.
+const MIN_VERSION: usize = 16;
+
+// Structure block tokens.
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+
+/// Nesting depth up to which `#address-cells` and `#size-cells` are tracked.
+/// Deeper nodes inherit the values of the deepest tracked level.
+const MAX_DEPTH: usize = 16;
+
+/// Read a big-endian u32.
+fn be32(bytes: &[u8], offset: usize) -> Option<u32> {
+  let b = bytes.get(offset..offset + 4)?;
+  Some(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// Read a number made of `cells` big-endian u32s. Only up to two cells fit.
+fn read_cells(bytes: &[u8], cells: usize) -> Option<u64> {
+  if cells > 2 {
+    return None;
+  }
+
+  (0..cells).try_fold(0u64, |acc, i| Some((acc << 32) | be32(bytes, i * 4)? as u64))
+}
+
+/// Tokens and property values are padded to 4 bytes.
+const fn align4(offset: usize) -> usize {
+  (offset + 3) & !3
+}
+
+/// The NUL-terminated string at `offset`.
+fn c_str(bytes: &[u8], offset: usize) -> Option<&str> {
+  let bytes = bytes.get(offset..)?;
+  let len = bytes.iter().position(|b| *b == 0)?;
+  str::from_utf8(&bytes[..len]).ok()
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A parsed device tree blob.
+#[derive(Copy, Clone)]
+pub struct Fdt<'a> {
+  total_size: usize,
+  /// From the memory reservation block to the end of the blob.
+  mem_rsvmap: &'a [u8],
+  structs: &'a [u8],
+  strings: &'a [u8],
+}
+
+/// `#address-cells` and `#size-cells` of a node, which apply to its children.
+#[derive(Copy, Clone)]
+pub struct Cells {
+  pub address: usize,
+  pub size: usize,
+}
+
+/// A node in the tree.
+#[derive(Copy, Clone)]
+pub struct Node<'a> {
+  fdt: Fdt<'a>,
+  name: &'a str,
+  depth: usize,
+  /// Offset of the first token after the node name in the structure block.
+  offset: usize,
+  /// Cells of the parent, used to decode `reg`.
+  parent_cells: Cells,
+}
+
+/// Iterator over all nodes, in the order they appear in the blob.
+pub struct Nodes<'a> {
+  fdt: Fdt<'a>,
+  offset: usize,
+  depth: usize,
+  cells: [Cells; MAX_DEPTH],
+}
+
+/// Iterator over the properties of a node, as `(name, value)`.
+pub struct Properties<'a> {
+  fdt: Fdt<'a>,
+  offset: usize,
+}
+
+/// Iterator over the memory reservation block, as `(address, size)` pairs.
+pub struct MemReservations<'a> {
+  value: &'a [u8],
+}
+
+/// An `(address, size)` pair from a `reg` property or a memory reservation.
+#[derive(Copy, Clone, Debug)]
+pub struct RegEntry {
+  pub address: u64,
+  pub size: u64,
+}
+
+/// Iterator over the entries of a `reg` property.
+pub struct Reg<'a> {
+  value: &'a [u8],
+  cells: Cells,
+}
+
+/// One entry of a `ranges` property. Maps `size` bytes at `child_address` on
+/// the node's bus to `parent_address` on the parent's bus.
+#[derive(Copy, Clone, Debug)]
+pub struct RangesEntry {
+  pub child_address: u64,
+  pub parent_address: u64,
+  pub size: u64,
+}
+
+/// Iterator over the entries of a `ranges` property.
+pub struct Ranges<'a> {
+  value: &'a [u8],
+  child_cells: Cells,
+  parent_address_cells: usize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl Cells {
+  /// What the specification assumes for nodes without the properties.
+  pub const DEFAULT: Self = Self { address: 2, size: 1 };
+}
+
+impl<'a> Fdt<'a> {
+  /// Parse the header of the blob in `data` and check that its blocks are in
+  /// bounds.
+  pub fn new(data: &'a [u8]) -> Result<Self, &'static str> {
+    if be32(data, 0) != Some(FDT_MAGIC) {
+      return Err("Bad magic");
+    }
+
+    let header = |field: usize| {
+      be32(data, field * 4)
+        .map(|value| value as usize)
+        .ok_or("Truncated header")
+    };
+
+    let total_size = header(1)?;
+    let off_dt_struct = header(2)?;
+    let off_dt_strings = header(3)?;
+    let off_mem_rsvmap = header(4)?;
+    let version = header(5)?;
+    let size_dt_strings = header(8)?;
+    let size_dt_struct = header(9)?;
+
+    if version < MIN_VERSION {
+      return Err("Unsupported version");
+    }
+
+    let data = data.get(..total_size).ok_or("Blob larger than the buffer")?;
+
+    Ok(Self {
+      total_size,
+      mem_rsvmap: data
+        .get(off_mem_rsvmap..)
+        .ok_or("Memory reservation block out of bounds")?,
+      structs: data
+        .get(off_dt_struct..off_dt_struct + size_dt_struct)
+        .ok_or("Structure block out of bounds")?,
+      strings: data
+        .get(off_dt_strings..off_dt_strings + size_dt_strings)
+        .ok_or("Strings block out of bounds")?,
+    })
+  }
+
+  /// Parse the blob the firmware left at `addr`.
+  ///
+  /// # Safety
+  ///
+  /// - `addr` must be null or point to readable memory holding a DTB, which
+  ///   must not be changed for as long as the kernel runs.
+  pub unsafe fn from_addr(addr: usize) -> Result<Fdt<'static>, &'static str> {
+    if addr == 0 {
+      return Err("No blob");
+    }
+
+    let header = core::slice::from_raw_parts(addr as *const u8, HEADER_SIZE);
+    if be32(header, 0) != Some(FDT_MAGIC) {
+      return Err("Bad magic");
+    }
+
+    let total_size = be32(header, 4).unwrap_or(0) as usize;
+    Fdt::new(core::slice::from_raw_parts(addr as *const u8, total_size))
+  }
+
+  /// Size of the whole blob in bytes.
+  pub fn total_size(&self) -> usize {
+    self.total_size
+  }
+
+  /// All nodes, in the order they appear in the blob. The root node comes
+  /// first.
+  pub fn nodes(&self) -> Nodes<'a> {
+    Nodes {
+      fdt: *self,
+      offset: 0,
+      depth: 0,
+      cells: [Cells::DEFAULT; MAX_DEPTH],
+    }
+  }
+
+  /// The node at an absolute path like `/soc` or `/memory@0`. Path components
+  /// without a unit address also match nodes that have one.
+  pub fn find_node(&self, path: &str) -> Option<Node<'a>> {
+    let mut components = path.split('/').filter(|c| !c.is_empty());
+    let mut wanted = components.next();
+    let mut matched_depth = 1;
+
+    if wanted.is_none() {
+      return self.nodes().next();
+    }
+
+    for node in self.nodes().skip(1) {
+      // Left the subtree of the last matched component.
+      if node.depth <= matched_depth {
+        return None;
+      }
+
+      if node.depth == matched_depth + 1 && node.has_name(wanted?) {
+        wanted = components.next();
+        if wanted.is_none() {
+          return Some(node);
+        }
+        matched_depth += 1;
+      }
+    }
+
+    None
+  }
+
+  /// The first node compatible with `compatible`.
+  pub fn find_compatible(&self, compatible: &str) -> Option<Node<'a>> {
+    self.nodes().find(|node| node.is_compatible(compatible))
+  }
+
+  /// The physical memory ranges the `/memory` nodes describe.
+  pub fn memory_ranges(&self) -> impl Iterator<Item = RegEntry> + 'a {
+    self
+      .nodes()
+      .filter(|node| node.depth == 2 && node.property_str("device_type") == Some("memory"))
+      .flat_map(|node| node.reg())
+  }
+
+  /// The memory the `/memreserve/` entries keep from the kernel.
+  pub fn mem_reservations(&self) -> MemReservations<'a> {
+    MemReservations { value: self.mem_rsvmap }
+  }
+
+  /// Translate an address on the `/soc` bus, as used in the `reg` of
+  /// peripherals, to a CPU physical address.
+  pub fn translate_soc_address(&self, address: u64) -> Option<u64> {
+    self.find_node("/soc")?.translate(address)
+  }
+
+  fn string(&self, offset: usize) -> Option<&'a str> {
+    c_str(self.strings, offset)
+  }
+}
+
+impl<'a> Node<'a> {
+  /// The full node name, including the unit address.
+  pub fn name(&self) -> &'a str {
+    self.name
+  }
+
+  /// Whether the node is called `name`. A name without unit address matches
+  /// any unit address.
+  pub fn has_name(&self, name: &str) -> bool {
+    self.name == name || (!name.contains('@') && self.name.split('@').next() == Some(name))
+  }
+
+  pub fn properties(&self) -> Properties<'a> {
+    Properties {
+      fdt: self.fdt,
+      offset: self.offset,
+    }
+  }
+
+  /// Raw value of the property `name`.
+  pub fn property(&self, name: &str) -> Option<&'a [u8]> {
+    self.properties().find(|(n, _)| *n == name).map(|(_, value)| value)
+  }
+
+  /// Value of a string property, without the terminating NUL.
+  pub fn property_str(&self, name: &str) -> Option<&'a str> {
+    let value = self.property(name)?;
+    str::from_utf8(value.strip_suffix(&[0]).unwrap_or(value)).ok()
+  }
+
+  /// Value of a single cell property.
+  pub fn property_u32(&self, name: &str) -> Option<u32> {
+    be32(self.property(name)?, 0)
+  }
+
+  /// Whether `compatible` is in the node's compatible string list.
+  pub fn is_compatible(&self, compatible: &str) -> bool {
+    self.property("compatible").map_or(false, |list| {
+      list.split(|b| *b == 0).any(|c| c == compatible.as_bytes())
+    })
+  }
+
+  /// The cells the node declares for its children.
+  pub fn cells(&self) -> Cells {
+    Cells {
+      address: self
+        .property_u32("#address-cells")
+        .map_or(Cells::DEFAULT.address, |c| c as usize),
+      size: self
+        .property_u32("#size-cells")
+        .map_or(Cells::DEFAULT.size, |c| c as usize),
+    }
+  }
+
+  /// The entries of the node's `reg` property, in the parent's address space.
+  pub fn reg(&self) -> Reg<'a> {
+    Reg {
+      value: self.property("reg").unwrap_or(&[]),
+      cells: self.parent_cells,
+    }
+  }
+
+  /// The entries of the node's `ranges` property.
+  pub fn ranges(&self) -> Ranges<'a> {
+    Ranges {
+      value: self.property("ranges").unwrap_or(&[]),
+      child_cells: self.cells(),
+      parent_address_cells: self.parent_cells.address,
+    }
+  }
+
+  /// Translate an address on the bus this node provides to the parent's bus.
+  /// An empty `ranges` means both are the same, a missing one means there is
+  /// no translation.
+  pub fn translate(&self, address: u64) -> Option<u64> {
+    match self.property("ranges") {
+      None => None,
+      Some([]) => Some(address),
+      Some(_) => self
+        .ranges()
+        .find(|r| address >= r.child_address && address - r.child_address < r.size)
+        .map(|r| r.parent_address + (address - r.child_address)),
+    }
+  }
+}
+
+impl<'a> Iterator for Nodes<'a> {
+  type Item = Node<'a>;
+
+  fn next(&mut self) -> Option<Node<'a>> {
+    let structs = self.fdt.structs;
+
+    loop {
+      match be32(structs, self.offset)? {
+        FDT_BEGIN_NODE => {
+          let name = c_str(structs, self.offset + 4)?;
+          self.offset = align4(self.offset + 4 + name.len() + 1);
+
+          let parent_cells = self.cells[self.depth.min(MAX_DEPTH - 1)];
+          self.depth += 1;
+          if self.depth < MAX_DEPTH {
+            self.cells[self.depth] = Cells::DEFAULT;
+          }
+
+          return Some(Node {
+            fdt: self.fdt,
+            name,
+            depth: self.depth,
+            offset: self.offset,
+            parent_cells,
+          });
+        },
+        FDT_END_NODE => {
+          self.depth = self.depth.checked_sub(1)?;
+          self.offset += 4;
+        },
+        FDT_PROP => {
+          let len = be32(structs, self.offset + 4)? as usize;
+          let name = self.fdt.string(be32(structs, self.offset + 8)? as usize)?;
+          let value = structs.get(self.offset + 12..self.offset + 12 + len)?;
+          self.offset = align4(self.offset + 12 + len);
+
+          // The node's cells apply to its children, which come after all of
+          // its properties.
+          if self.depth < MAX_DEPTH {
+            match name {
+              "#address-cells" => self.cells[self.depth].address = be32(value, 0)? as usize,
+              "#size-cells" => self.cells[self.depth].size = be32(value, 0)? as usize,
+              _ => (),
+            }
+          }
+        },
+        FDT_NOP => self.offset += 4,
+        // FDT_END, or garbage.
+        _ => return None,
+      }
+    }
+  }
+}
+
+impl<'a> Iterator for Properties<'a> {
+  type Item = (&'a str, &'a [u8]);
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let structs = self.fdt.structs;
+
+    loop {
+      match be32(structs, self.offset)? {
+        FDT_PROP => {
+          let len = be32(structs, self.offset + 4)? as usize;
+          let name = self.fdt.string(be32(structs, self.offset + 8)? as usize)?;
+          let value = structs.get(self.offset + 12..self.offset + 12 + len)?;
+          self.offset = align4(self.offset + 12 + len);
+
+          return Some((name, value));
+        },
+        FDT_NOP => self.offset += 4,
+        // Properties come before subnodes, so anything else ends the list.
+        _ => return None,
+      }
+    }
+  }
+}
+
+impl Iterator for MemReservations<'_> {
+  type Item = RegEntry;
+
+  fn next(&mut self) -> Option<RegEntry> {
+    let entry = RegEntry {
+      address: read_cells(self.value, 2)?,
+      size: read_cells(self.value.get(8..)?, 2)?,
+    };
+    // An empty entry ends the block.
+    if entry.size == 0 {
+      return None;
+    }
+    self.value = &self.value[16..];
+
+    Some(entry)
+  }
+}
+
+impl Iterator for Reg<'_> {
+  type Item = RegEntry;
+
+  fn next(&mut self) -> Option<RegEntry> {
+    let entry_size = (self.cells.address + self.cells.size) * 4;
+    if entry_size == 0 || self.value.len() < entry_size {
+      return None;
+    }
+
+    let entry = RegEntry {
+      address: read_cells(self.value, self.cells.address)?,
+      size: read_cells(&self.value[self.cells.address * 4..], self.cells.size)?,
+    };
+    self.value = &self.value[entry_size..];
+
+    Some(entry)
+  }
+}
+
+impl Iterator for Ranges<'_> {
+  type Item = RangesEntry;
+
+  fn next(&mut self) -> Option<RangesEntry> {
+    let child = self.child_cells.address * 4;
+    let parent = self.parent_address_cells * 4;
+    let entry_size = child + parent + self.child_cells.size * 4;
+    if entry_size == 0 || self.value.len() < entry_size {
+      return None;
+    }
+
+    let entry = RangesEntry {
+      child_address: read_cells(self.value, self.child_cells.address)?,
+      parent_address: read_cells(&self.value[child..], self.parent_address_cells)?,
+      size: read_cells(&self.value[child + parent..], self.child_cells.size)?,
+    };
+    self.value = &self.value[entry_size..];
+
+    Some(entry)
+  }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// A device tree from the Raspberry Pi firmware, see `make test`.
+  fn blob(name: &str) -> Vec<u8> {
+    let path = format!("{}/tests/dtb/{}", env!("CARGO_MANIFEST_DIR"), name);
+    std::fs::read(&path).unwrap_or_else(|err| panic!("{}: {}, fetch it with `make test`", path, err))
+  }
+
+  fn rpi3() -> Vec<u8> {
+    blob("bcm2710-rpi-3-b.dtb")
+  }
+
+  fn rpi4() -> Vec<u8> {
+    blob("bcm2711-rpi-4-b.dtb")
+  }
+
+  fn mmio_base(fdt: &Fdt, compatible: &str, index: usize) -> Option<u64> {
+    let reg = fdt.find_compatible(compatible)?.reg().nth(index)?;
+    fdt.translate_soc_address(reg.address)
+  }
+
+  #[test]
+  fn header() {
+    let rpi3 = rpi3();
+    let fdt = Fdt::new(&rpi3).unwrap();
+    assert_eq!(fdt.total_size(), rpi3.len());
+
+    let root = fdt.find_node("/").unwrap();
+    assert!(root.is_compatible("raspberrypi,3-model-b"));
+    assert!(root.is_compatible("brcm,bcm2837"));
+    assert!(!root.is_compatible("brcm,bcm2711"));
+  }
+
+  #[test]
+  fn memory_ranges() {
+    // The firmware fills in the sizes at boot.
+    let rpi3 = rpi3();
+    let fdt = Fdt::new(&rpi3).unwrap();
+    assert_eq!(fdt.memory_ranges().map(|r| r.address).collect::<Vec<_>>(), [0]);
+
+    let rpi4 = rpi4();
+    let fdt = Fdt::new(&rpi4).unwrap();
+    let cells = fdt.find_node("/").unwrap().cells();
+    assert_eq!((cells.address, cells.size), (2, 1));
+    assert_eq!(fdt.memory_ranges().map(|r| r.address).collect::<Vec<_>>(), [0]);
+  }
+
+  #[test]
+  fn soc_address_translation() {
+    let rpi3 = rpi3();
+    let rpi3 = Fdt::new(&rpi3).unwrap();
+    assert_eq!(rpi3.translate_soc_address(0x7e00_b880), Some(0x3f00_b880));
+    assert_eq!(rpi3.translate_soc_address(0x4000_0000), Some(0x4000_0000));
+    assert_eq!(rpi3.translate_soc_address(0x7f00_0000), None);
+
+    let rpi4 = rpi4();
+    let rpi4 = Fdt::new(&rpi4).unwrap();
+    assert_eq!(rpi4.translate_soc_address(0x7e00_b880), Some(0xfe00_b880));
+    assert_eq!(rpi4.translate_soc_address(0x7c00_0000), Some(0xfc00_0000));
+    assert_eq!(rpi4.translate_soc_address(0x4004_1000), Some(0xff84_1000));
+    assert_eq!(rpi4.translate_soc_address(0x8000_0000), None);
+  }
+
+  #[test]
+  fn find_compatible() {
+    let rpi3 = rpi3();
+    let rpi3 = Fdt::new(&rpi3).unwrap();
+    let mailbox = rpi3.find_compatible("brcm,bcm2835-mbox").unwrap();
+    assert_eq!(mailbox.name(), "mailbox@7e00b880");
+    assert_eq!(
+      mailbox.reg().map(|r| (r.address, r.size)).collect::<Vec<_>>(),
+      [(0x7e00_b880, 0x40)]
+    );
+    assert_eq!(mmio_base(&rpi3, "brcm,bcm2835-mbox", 0), Some(0x3f00_b880));
+    assert_eq!(mmio_base(&rpi3, "brcm,bcm2836-armctrl-ic", 0), Some(0x3f00_b200));
+    assert_eq!(mmio_base(&rpi3, "brcm,bcm2836-l1-intc", 0), Some(0x4000_0000));
+    assert_eq!(mmio_base(&rpi3, "brcm,bcm2835-system-timer", 0), Some(0x3f00_3000));
+    assert!(rpi3.find_compatible("arm,gic-400").is_none());
+
+    let rpi4 = rpi4();
+    let rpi4 = Fdt::new(&rpi4).unwrap();
+    assert_eq!(mmio_base(&rpi4, "brcm,bcm2835-mbox", 0), Some(0xfe00_b880));
+    assert_eq!(mmio_base(&rpi4, "arm,pl011", 0), Some(0xfe20_1000));
+    assert_eq!(mmio_base(&rpi4, "arm,gic-400", 0), Some(0xff84_1000));
+    assert_eq!(mmio_base(&rpi4, "arm,gic-400", 1), Some(0xff84_2000));
+  }
+
+  #[test]
+  fn find_node() {
+    let rpi4 = rpi4();
+    let rpi4 = Fdt::new(&rpi4).unwrap();
+    assert_eq!(rpi4.find_node("/").unwrap().name(), "");
+    assert_eq!(rpi4.find_node("/memory").unwrap().name(), "memory@0");
+    assert_eq!(rpi4.find_node("/soc/mailbox").unwrap().name(), "mailbox@7e00b880");
+    assert!(rpi4.find_node("/chosen").is_some());
+    assert!(rpi4.find_node("/mailbox").is_none());
+    assert!(rpi4.find_node("/cpus/mailbox").is_none());
+
+    // A bus with other cells than `/soc`.
+    let pcie = rpi4.find_compatible("brcm,bcm2711-pcie").unwrap();
+    assert_eq!(
+      pcie.reg().map(|r| (r.address, r.size)).collect::<Vec<_>>(),
+      [(0x7d50_0000, 0x9310)]
+    );
+  }
+
+  #[test]
+  fn mem_reservations() {
+    // Move the block to the end, with two entries.
+    let mut blob = rpi3();
+    let off_mem_rsvmap = blob.len() as u32;
+    for value in [0x0, 0x1000, 0x3b40_0000, 0x4c0_0000, 0, 0] {
+      blob.extend_from_slice(&u64::to_be_bytes(value));
+    }
+    let total_size = blob.len() as u32;
+    blob[4..8].copy_from_slice(&total_size.to_be_bytes());
+    blob[16..20].copy_from_slice(&off_mem_rsvmap.to_be_bytes());
+
+    let fdt = Fdt::new(&blob).unwrap();
+    assert_eq!(
+      fdt.mem_reservations().map(|r| (r.address, r.size)).collect::<Vec<_>>(),
+      [(0x0, 0x1000), (0x3b40_0000, 0x4c0_0000)]
+    );
+
+    // Without the terminating entry.
+    assert_eq!(
+      Fdt::new(&blob[..blob.len() - 16]).err(),
+      Some("Blob larger than the buffer")
+    );
+    let mut blob = blob[..blob.len() - 16].to_vec();
+    blob[4..8].copy_from_slice(&(total_size - 16).to_be_bytes());
+    assert_eq!(Fdt::new(&blob).unwrap().mem_reservations().count(), 2);
+  }
+
+  #[test]
+  fn bad_magic() {
+    let mut blob = rpi3();
+    blob[0] = 0;
+    assert_eq!(Fdt::new(&blob).err(), Some("Bad magic"));
+    assert_eq!(Fdt::new(&[]).err(), Some("Bad magic"));
+  }
+
+  #[test]
+  fn truncated() {
+    let rpi3 = rpi3();
+    assert_eq!(Fdt::new(&rpi3[..8]).err(), Some("Truncated header"));
+    assert_eq!(
+      Fdt::new(&rpi3[..rpi3.len() / 2]).err(),
+      Some("Blob larger than the buffer")
+    );
+    assert_eq!(
+      Fdt::new(&rpi3[..rpi3.len() - 1]).err(),
+      Some("Blob larger than the buffer")
+    );
+  }
+
+  #[test]
+  fn blocks_out_of_bounds() {
+    // `size_dt_struct` past the end of the blob.
+    let mut blob = rpi3();
+    blob[36..40].copy_from_slice(&u32::MAX.to_be_bytes());
+    assert_eq!(Fdt::new(&blob).err(), Some("Structure block out of bounds"));
+  }
+
+  #[test]
+  fn corrupt_structure_block() {
+    // The length of the root's first property, now running past the block.
+    let mut blob = rpi3();
+    let off_dt_struct = u32::from_be_bytes(blob[8..12].try_into().unwrap()) as usize;
+    blob[off_dt_struct + 12..off_dt_struct + 16].copy_from_slice(&u32::MAX.to_be_bytes());
+
+    let fdt = Fdt::new(&blob).unwrap();
+    assert_eq!(fdt.nodes().count(), 1);
+    assert_eq!(fdt.nodes().next().unwrap().properties().count(), 0);
+    assert!(fdt.find_compatible("brcm,bcm2835-mbox").is_none());
+    assert_eq!(fdt.memory_ranges().count(), 0);
+  }
+}
@@ -1,26 +1,61 @@
 use crate::bsp::framebuffer::FrameBuffer;
 use crate::bsp::mailbox::{send_property_messages, PropertyMessage};
-use crate::info;
+use crate::bsp::memory::map_framebuffer;
+use crate::cmdline::boot_options;
+use crate::mem::heap_range_inclusive;
+use crate::{info, warn};
+
+/// The firmware hands out VideoCore bus addresses, strip the cache alias bits
+/// to get the ARM physical address.
+const BUS_ADDRESS_MASK: u32 = 0x3FFF_FFFF;
+
+/// Used unless the `resolution=<width>x<height>` boot option says otherwise.
+const DEFAULT_RESOLUTION: (u32, u32) = (640, 480);
+
+/// Drawing copies 3 byte pixels, so this is fixed.
+const BITS_PER_PIXEL: u32 = 24;
 
 pub fn init_fb() -> FrameBuffer {
+  let (width, height) = boot_options()
+    .get_dimensions("resolution")
+    .unwrap_or(DEFAULT_RESOLUTION);
+
   let result = send_property_messages(&[
-    PropertyMessage::SetPhysicalDimensions(640, 480),
-    PropertyMessage::SetVirtualDimensions(640, 480),
-    PropertyMessage::SetBitsPerPixel(24),
+    PropertyMessage::SetPhysicalDimensions(width, height),
+    PropertyMessage::SetVirtualDimensions(width, height),
+    PropertyMessage::SetBitsPerPixel(BITS_PER_PIXEL),
   ]);
 
   if let Ok(dimensions) = result {
     let buffer = send_property_messages(&[PropertyMessage::AllocateBuffer(16)]);
 
     if let Ok(buffer) = buffer {
-      info!("Framebuffer located at {:#01x} size {:#01x}", buffer[5], buffer[6]);
+      let address = buffer[5] & BUS_ADDRESS_MASK;
+      info!("Framebuffer located at {:#01x} size {:#01x}", address, buffer[6]);
       info!(
         "Working space located at {:#01x} size {:#01x}",
-        buffer[5] + buffer[6],
+        address + buffer[6],
         buffer[6]
       );
 
-      return FrameBuffer::new(dimensions[5], dimensions[6], 24, buffer[5] as *mut u32, buffer[6]);
+      // Drawing goes to the working space right behind the framebuffer.
+      let fb_range = address as usize..=address as usize + 2 * buffer[6] as usize - 1;
+      let heap_range = heap_range_inclusive();
+      if fb_range.start() <= heap_range.end() && heap_range.start() <= fb_range.end() {
+        panic!("Framebuffer overlaps the heap");
+      }
+
+      if let Err(err) = map_framebuffer(address as usize, buffer[6] as usize) {
+        warn!("Failed to map framebuffer write-combining: {}", err);
+      }
+
+      return FrameBuffer::new(
+        dimensions[5],
+        dimensions[6],
+        BITS_PER_PIXEL,
+        address as *mut u32,
+        buffer[6],
+      );
     }
   }
 
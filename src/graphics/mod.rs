@@ -1,4 +1,6 @@
 mod init;
+pub mod scheduler;
+pub mod stats;
 pub mod ui;
 
 pub use init::*;
@@ -0,0 +1,132 @@
+//! Frame scheduling.
+//!
+//! The simulation advances in fixed steps of `1 / tick rate`, independent of
+//! how fast frames are drawn. The time between frames goes into an
+//! accumulator, and `on_tick` runs once for every whole step in it. The rest
+//! is handed to `draw` as the interpolation alpha: how far real time is into
+//! the next step.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use core::time::Duration;
+
+use super::stats::{self, FrameTiming};
+use super::ui::UiInterface;
+use crate::bsp::alloc::ALLOCATOR;
+use crate::bsp::framebuffer::FrameBuffer;
+use crate::time::{self, Instant};
+use crate::{profile, profile_zone, task};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Steps run per frame at most. After a stall, the rest of the backlog is
+/// dropped instead of stalling the next frames, too.
+const MAX_CATCH_UP_TICKS: u32 = 5;
+
+static TARGET_FPS: AtomicU32 = AtomicU32::new(DEFAULT_TARGET_FPS);
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Used unless the `fps` boot option says otherwise.
+pub const DEFAULT_TARGET_FPS: u32 = 60;
+
+/// Used unless the `tick_rate` boot option says otherwise.
+pub const DEFAULT_TICK_RATE: u32 = 60;
+
+/// Runs the frame loop.
+pub struct FrameScheduler {
+  tick: Duration,
+  accumulator: Duration,
+  last_time: Instant,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Set the frame rate to aim for. Takes effect with the next frame, 0 restores
+/// the default.
+pub fn set_target_fps(fps: u32) {
+  let fps = if fps == 0 { DEFAULT_TARGET_FPS } else { fps };
+  TARGET_FPS.store(fps, Ordering::Relaxed);
+}
+
+pub fn target_fps() -> u32 {
+  TARGET_FPS.load(Ordering::Relaxed)
+}
+
+impl FrameScheduler {
+  /// A scheduler stepping the simulation `tick_rate` times per second.
+  pub fn new(tick_rate: u32) -> Self {
+    Self {
+      tick: Duration::from_secs(1) / tick_rate.max(1),
+      accumulator: Duration::ZERO,
+      last_time: Instant::now(),
+    }
+  }
+
+  /// Drive `ui` and present to `fb`, forever.
+  pub fn run(&mut self, ui: &mut impl UiInterface, fb: &mut FrameBuffer) -> ! {
+    loop {
+      self.frame(ui, fb);
+    }
+  }
+
+  /// Wait for the next frame, catch the simulation up and draw.
+  pub fn frame(&mut self, ui: &mut impl UiInterface, fb: &mut FrameBuffer) {
+    let idle_start = Instant::now();
+    let frame_interval = Duration::from_secs(1) / target_fps();
+    time::timers::run_until(self.last_time + frame_interval);
+
+    let now = Instant::now();
+    self.accumulator += now.duration_since(self.last_time);
+    self.last_time = now;
+
+    {
+      profile_zone!("update");
+
+      {
+        profile_zone!("tasks");
+        task::run_ready();
+      }
+
+      let mut ticks = 0;
+      while self.accumulator >= self.tick {
+        if ticks == MAX_CATCH_UP_TICKS {
+          // Keep the fraction, so the alpha stays continuous.
+          self.accumulator = Duration::from_nanos((self.accumulator.as_nanos() % self.tick.as_nanos()) as u64);
+          break;
+        }
+
+        ui.on_tick(self.tick);
+        self.accumulator -= self.tick;
+        ticks += 1;
+      }
+    }
+    let updated = Instant::now();
+
+    if ui.should_draw() {
+      profile_zone!("draw");
+      ui.draw(fb, self.accumulator.as_secs_f32() / self.tick.as_secs_f32());
+    }
+    let drawn = Instant::now();
+
+    {
+      profile_zone!("present");
+      fb.update_fb();
+    }
+    let presented = Instant::now();
+
+    stats::record(FrameTiming {
+      update: updated - now,
+      draw: drawn - updated,
+      present: presented - drawn,
+      idle: now - idle_start,
+    });
+    profile::end_frame();
+    ALLOCATOR.end_frame();
+  }
+}
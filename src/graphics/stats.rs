@@ -0,0 +1,199 @@
+//! Frame statistics.
+//!
+//! The frame scheduler records where the time of each frame went. The last
+//! `HISTORY_LEN` frames are kept, and `summary` condenses them for display.
+
+use core::fmt;
+use core::time::Duration;
+
+use crate::cpu::SpinLock;
+use crate::info;
+use crate::time::timers::{self, TimerHandle};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Frames kept for the summary, a few seconds worth.
+const HISTORY_LEN: usize = 256;
+
+struct FrameHistory {
+  timings: [FrameTiming; HISTORY_LEN],
+  /// Slot the next frame goes into.
+  next: usize,
+  len: usize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Histogram buckets are this wide, the last one takes everything longer.
+pub const HISTOGRAM_BUCKET_WIDTH: Duration = Duration::from_millis(4);
+pub const HISTOGRAM_BUCKETS: usize = 16;
+
+/// Where the time of one frame went.
+#[derive(Copy, Clone, Default)]
+pub struct FrameTiming {
+  /// Running `on_tick`.
+  pub update: Duration,
+  pub draw: Duration,
+  /// `FrameBuffer::update_fb`.
+  pub present: Duration,
+  /// Waiting for the frame to be due.
+  pub idle: Duration,
+}
+
+/// Statistics over the recorded frames.
+#[derive(Copy, Clone, Default)]
+pub struct FrameSummary {
+  pub frames: usize,
+  pub min: Duration,
+  pub avg: Duration,
+  pub max: Duration,
+  /// Frame rate over the slowest 1% of frames.
+  pub one_percent_low_fps: f32,
+  /// Averages of the parts.
+  pub avg_timing: FrameTiming,
+  /// Frame counts by frame time.
+  pub histogram: [u32; HISTOGRAM_BUCKETS],
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static FRAME_HISTORY: SpinLock<FrameHistory> = SpinLock::new(
+  "frame history",
+  FrameHistory {
+    timings: [FrameTiming::ZERO; HISTORY_LEN],
+    next: 0,
+    len: 0,
+  },
+);
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl FrameTiming {
+  const ZERO: Self = Self {
+    update: Duration::ZERO,
+    draw: Duration::ZERO,
+    present: Duration::ZERO,
+    idle: Duration::ZERO,
+  };
+
+  /// The whole frame.
+  pub fn total(&self) -> Duration {
+    self.update + self.draw + self.present + self.idle
+  }
+}
+
+impl FrameSummary {
+  /// Average frame rate.
+  pub fn fps(&self) -> f32 {
+    match self.avg.as_secs_f32() {
+      secs if secs > 0.0 => 1.0 / secs,
+      _ => 0.0,
+    }
+  }
+}
+
+impl fmt::Display for FrameSummary {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let ms = |duration: Duration| duration.as_secs_f32() * 1000.0;
+
+    write!(
+      f,
+      "{} frames, {:.1} FPS, 1% low {:.1} FPS, frame min/avg/max {:.2}/{:.2}/{:.2} ms, \
+       update {:.2} ms, draw {:.2} ms, present {:.2} ms, idle {:.2} ms, histogram",
+      self.frames,
+      self.fps(),
+      self.one_percent_low_fps,
+      ms(self.min),
+      ms(self.avg),
+      ms(self.max),
+      ms(self.avg_timing.update),
+      ms(self.avg_timing.draw),
+      ms(self.avg_timing.present),
+      ms(self.avg_timing.idle),
+    )?;
+
+    for count in self.histogram {
+      write!(f, " {}", count)?;
+    }
+    Ok(())
+  }
+}
+
+/// Add a frame to the history, replacing the oldest one if it is full.
+pub fn record(timing: FrameTiming) {
+  let mut history = FRAME_HISTORY.lock();
+
+  let next = history.next;
+  history.timings[next] = timing;
+  history.next = (next + 1) % HISTORY_LEN;
+  history.len = (history.len + 1).min(HISTORY_LEN);
+}
+
+/// Log the summary every `interval`.
+pub fn start_periodic_summary(interval: Duration) -> TimerHandle {
+  timers::start_periodic(interval, || info!("Frames: {}", summary()))
+}
+
+/// Statistics over the recorded frames.
+pub fn summary() -> FrameSummary {
+  // Sorting takes a while, don't hold the lock meanwhile.
+  let (timings, len) = {
+    let history = FRAME_HISTORY.lock();
+    (history.timings, history.len)
+  };
+  let timings = &timings[..len];
+
+  if timings.is_empty() {
+    return FrameSummary::default();
+  }
+
+  let mut summary = FrameSummary {
+    frames: len,
+    min: Duration::MAX,
+    ..FrameSummary::default()
+  };
+
+  let mut totals = [Duration::ZERO; HISTORY_LEN];
+  let mut sum = FrameTiming::ZERO;
+  for (timing, total) in timings.iter().zip(totals.iter_mut()) {
+    *total = timing.total();
+    summary.min = summary.min.min(*total);
+    summary.max = summary.max.max(*total);
+
+    sum.update += timing.update;
+    sum.draw += timing.draw;
+    sum.present += timing.present;
+    sum.idle += timing.idle;
+
+    let bucket = (total.as_nanos() / HISTOGRAM_BUCKET_WIDTH.as_nanos()) as usize;
+    summary.histogram[bucket.min(HISTOGRAM_BUCKETS - 1)] += 1;
+  }
+
+  let frames = len as u32;
+  summary.avg = sum.total() / frames;
+  summary.avg_timing = FrameTiming {
+    update: sum.update / frames,
+    draw: sum.draw / frames,
+    present: sum.present / frames,
+    idle: sum.idle / frames,
+  };
+
+  // The slowest 1%, at least one frame.
+  let totals = &mut totals[..len];
+  totals.sort_unstable();
+  let slowest = &totals[len - (len + 99) / 100..];
+  let slowest_avg = slowest.iter().sum::<Duration>() / slowest.len() as u32;
+  if slowest_avg > Duration::ZERO {
+    summary.one_percent_low_fps = 1.0 / slowest_avg.as_secs_f32();
+  }
+
+  summary
+}
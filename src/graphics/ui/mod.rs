@@ -1,15 +1,25 @@
 use core::time::Duration;
 
 use crate::bsp::framebuffer::FrameBuffer;
+use crate::warn;
 
 pub trait UiInterface {
-  fn draw(&mut self, fb: &mut FrameBuffer);
+  /// Draw the current state. `alpha` in [0, 1) is how far real time is past
+  /// the last tick, in ticks, for interpolating between the last two states.
+  fn draw(&mut self, fb: &mut FrameBuffer, alpha: f32);
   fn should_draw(&self) -> bool;
   fn on_input(&mut self);
+  /// Advance the simulation by one step. `dt` is the same on every call.
   fn on_tick(&mut self, dt: Duration);
 }
 
-pub fn get_ui_entrypoint() -> impl UiInterface {
+/// The scene named by the `scene` boot option, `start` if there is none.
+pub fn get_ui_entrypoint(scene: &str) -> impl UiInterface {
+  match scene {
+    "start" => (),
+    _ => warn!("Unknown scene `{}`, starting with `start`", scene),
+  }
+
   StartInterface::default()
 }
 
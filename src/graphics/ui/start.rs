@@ -11,14 +11,13 @@ use embedded_graphics::Drawable;
 
 use super::UiInterface;
 use crate::bsp::framebuffer::FrameBuffer;
+use crate::graphics::stats;
 
 #[derive(Default)]
-pub struct StartInterface {
-  pub fps: f32,
-}
+pub struct StartInterface;
 
 impl UiInterface for StartInterface {
-  fn draw(&mut self, fb: &mut FrameBuffer) {
+  fn draw(&mut self, fb: &mut FrameBuffer, _alpha: f32) {
     let style = MonoTextStyle::new(&FONT_9X18_BOLD, Rgb888::WHITE);
     let (x, mut y) = (15, 15 + 9);
 
@@ -27,17 +26,28 @@ impl UiInterface for StartInterface {
     y += 20;
     title_text.draw(fb).unwrap();
 
-    let fps = format!("FPS: {:.2}", self.fps);
-    let text = Text::new(&fps, Point::new(x, y), style);
-    fb.fill_solid(&text.bounding_box(), Rgb888::BLACK).unwrap();
-    text.draw(fb).unwrap();
+    let summary = stats::summary();
+    let lines = [
+      format!("FPS: {:.2} (1% low {:.2})", summary.fps(), summary.one_percent_low_fps),
+      format!(
+        "Frame: {:.2}/{:.2}/{:.2} ms",
+        summary.min.as_secs_f32() * 1000.0,
+        summary.avg.as_secs_f32() * 1000.0,
+        summary.max.as_secs_f32() * 1000.0
+      ),
+    ];
+
+    for line in &lines {
+      let text = Text::new(line, Point::new(x, y), style);
+      fb.fill_solid(&text.bounding_box(), Rgb888::BLACK).unwrap();
+      text.draw(fb).unwrap();
+      y += 20;
+    }
   }
 
   fn on_input(&mut self) {}
 
-  fn on_tick(&mut self, dt: Duration) {
-    self.fps = 1.0 / dt.as_secs_f32()
-  }
+  fn on_tick(&mut self, _dt: Duration) {}
 
   fn should_draw(&self) -> bool {
     true
@@ -1,10 +1,45 @@
 //! Console Printing
 
 use core::fmt;
+use core::str::FromStr;
+use core::sync::atomic::{AtomicU8, Ordering};
 
 use super::console;
 use crate::bsp;
 
+/// How chatty `info!` and `warn!` are.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+  Off,
+  Warn,
+  Info,
+}
+
+static LOG_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+impl FromStr for LogLevel {
+  type Err = ();
+
+  fn from_str(s: &str) -> Result<Self, ()> {
+    match s {
+      "off" => Ok(Self::Off),
+      "warn" => Ok(Self::Warn),
+      "info" => Ok(Self::Info),
+      _ => Err(()),
+    }
+  }
+}
+
+/// Only print messages up to `level`.
+pub fn set_log_level(level: LogLevel) {
+  LOG_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+#[doc(hidden)]
+pub fn _log_enabled(level: LogLevel) -> bool {
+  level as u8 <= LOG_LEVEL.load(Ordering::Relaxed)
+}
+
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
   use console::interface::Write;
@@ -63,33 +98,31 @@ macro_rules! panic_println {
 #[macro_export]
 macro_rules! info {
   ($string:expr) => ({
-    #[allow(unused_imports)]
-    use crate::time::interface::TimeManager;
-
-    let timestamp = $crate::time::time_manager().uptime();
-    let timestamp_subsec_us = timestamp.subsec_micros();
-
-    $crate::io::print::_print(format_args_nl!(
-      concat!("[I {:>3}.{:03}{:03}] ", $string),
-      timestamp.as_secs(),
-      timestamp_subsec_us / 1_000,
-      timestamp_subsec_us % 1_000
-    ));
+    if $crate::io::print::_log_enabled($crate::io::print::LogLevel::Info) {
+      let timestamp = $crate::time::Instant::now().since_power_on();
+      let timestamp_subsec_us = timestamp.subsec_micros();
+
+      $crate::io::print::_print(format_args_nl!(
+        concat!("[I {:>3}.{:03}{:03}] ", $string),
+        timestamp.as_secs(),
+        timestamp_subsec_us / 1_000,
+        timestamp_subsec_us % 1_000
+      ));
+    }
   });
   ($format_string:expr, $($arg:tt)*) => ({
-    #[allow(unused_imports)]
-    use crate::time::interface::TimeManager;
-
-    let timestamp = $crate::time::time_manager().uptime();
-    let timestamp_subsec_us = timestamp.subsec_micros();
-
-    $crate::io::print::_print(format_args_nl!(
-      concat!("[I {:>3}.{:03}{:03}] ", $format_string),
-      timestamp.as_secs(),
-      timestamp_subsec_us / 1_000,
-      timestamp_subsec_us % 1_000,
-      $($arg)*
-    ));
+    if $crate::io::print::_log_enabled($crate::io::print::LogLevel::Info) {
+      let timestamp = $crate::time::Instant::now().since_power_on();
+      let timestamp_subsec_us = timestamp.subsec_micros();
+
+      $crate::io::print::_print(format_args_nl!(
+        concat!("[I {:>3}.{:03}{:03}] ", $format_string),
+        timestamp.as_secs(),
+        timestamp_subsec_us / 1_000,
+        timestamp_subsec_us % 1_000,
+        $($arg)*
+      ));
+    }
   })
 }
 
@@ -97,32 +130,30 @@ macro_rules! info {
 #[macro_export]
 macro_rules! warn {
   ($string:expr) => ({
-    #[allow(unused_imports)]
-    use crate::time::interface::TimeManager;
-
-    let timestamp = $crate::time::time_manager().uptime();
-    let timestamp_subsec_us = timestamp.subsec_micros();
-
-    $crate::io::print::_print(format_args_nl!(
-      concat!("[W {:>3}.{:03}{:03}] ", $string),
-      timestamp.as_secs(),
-      timestamp_subsec_us / 1_000,
-      timestamp_subsec_us % 1_000
-    ));
+    if $crate::io::print::_log_enabled($crate::io::print::LogLevel::Warn) {
+      let timestamp = $crate::time::Instant::now().since_power_on();
+      let timestamp_subsec_us = timestamp.subsec_micros();
+
+      $crate::io::print::_print(format_args_nl!(
+        concat!("[W {:>3}.{:03}{:03}] ", $string),
+        timestamp.as_secs(),
+        timestamp_subsec_us / 1_000,
+        timestamp_subsec_us % 1_000
+      ));
+    }
   });
   ($format_string:expr, $($arg:tt)*) => ({
-    #[allow(unused_imports)]
-    use crate::time::interface::TimeManager;
-
-    let timestamp = $crate::time::time_manager().uptime();
-    let timestamp_subsec_us = timestamp.subsec_micros();
-
-    $crate::io::print::_print(format_args_nl!(
-      concat!("[W {:>3}.{:03}{:03}] ", $format_string),
-      timestamp.as_secs(),
-      timestamp_subsec_us / 1_000,
-      timestamp_subsec_us % 1_000,
-      $($arg)*
-    ));
+    if $crate::io::print::_log_enabled($crate::io::print::LogLevel::Warn) {
+      let timestamp = $crate::time::Instant::now().since_power_on();
+      let timestamp_subsec_us = timestamp.subsec_micros();
+
+      $crate::io::print::_print(format_args_nl!(
+        concat!("[W {:>3}.{:03}{:03}] ", $format_string),
+        timestamp.as_secs(),
+        timestamp_subsec_us / 1_000,
+        timestamp_subsec_us % 1_000,
+        $($arg)*
+      ));
+    }
   })
 }
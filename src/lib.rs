@@ -0,0 +1,12 @@
+//! The parts of the kernel that don't touch the hardware.
+//!
+//! Built as a library next to the kernel binary, so they can be tested on the
+//! host with `make test`.
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+pub mod fdt;
+pub mod page_frames;
+pub mod timer_queue;
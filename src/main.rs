@@ -7,50 +7,124 @@
 
 use core::time::Duration;
 
-use crate::graphics::init_fb;
-use crate::graphics::ui::{get_ui_entrypoint, UiInterface};
+use crate::cmdline::boot_options;
+use crate::exception::asynchronous::interface::IRQManager;
+use crate::graphics::scheduler::{self, FrameScheduler};
+use crate::graphics::ui::get_ui_entrypoint;
+use crate::graphics::{init_fb, stats};
 use crate::mem::init_heap;
-use crate::time::interface::TimeManager;
-use crate::time::time_manager;
+use crate::mem::mmu::interface::Mmu;
+use crate::mem::mmu::mmu;
 
 extern crate alloc;
 
 mod bsp;
+mod cmdline;
 mod cpu;
+mod exception;
 mod graphics;
 mod io;
 mod mem;
 mod panic_wait;
+mod pmu;
+mod profile;
+mod task;
 mod time;
 
-const TARGET_FPS: u32 = 60;
-const TARGET_DT: f32 = 1.0 / TARGET_FPS as f32;
+/// Seconds between frame statistics on the console, unless the `frame_stats`
+/// boot option says otherwise. 0 turns them off.
+const DEFAULT_FRAME_STATS_INTERVAL_SECS: u32 = 10;
 
-unsafe fn kernel_main() -> ! {
-  // Init Heap
+unsafe fn kernel_main(dtb_addr: usize) -> ! {
+  // Install exception vectors before anything can fault
+  exception::handling_init();
+
+  // Caches only work with the MMU on, so do this before anything touches memory
+  // heavily
+  if let Err(err) = mmu().populate_translation_tables() {
+    panic!("MMU: {}", err);
+  }
+  if let Err(err) = mmu().enable_mmu_and_caching() {
+    panic!("MMU: {:?}", err);
+  }
+
+  // Find out where the peripherals are before any driver needs them. The heap
+  // and the page frames are laid out around the device tree, so find it first,
+  // too.
+  bsp::devicetree::init(dtb_addr);
+
+  // Init Heap. It is sized by asking the firmware, over the mailbox, which
+  // needs DMA memory
+  mem::dma::init();
   init_heap();
 
+  // Boot options need the heap. Apply the log level right away
+  cmdline::init();
+  if let Some(level) = boot_options().get_parsed("loglevel") {
+    io::set_log_level(level);
+  }
+  panic_wait::set_reboot_after_panic(boot_options().get_u32("panic_reboot"));
+
   info!("Hello from Rust!");
 
+  let (_, privilege_level) = exception::current_privilege_level();
+  info!("Current privilege level: {}", privilege_level);
+
+  bsp::memory::mmu::virt_mem_layout().print_layout();
+
+  // Nothing fires before drivers enable their IRQs
+  bsp::exception::asynchronous::init();
+  time::init();
+  pmu::init();
+  bsp::system_timer::init();
+  bsp::mailbox::init();
+  exception::asynchronous::irq_manager().print_handlers();
+  cpu::local_irq_unmask();
+
+  cpu::smp::start_secondary_cores();
+
   let mut fb = init_fb();
-  let mut current_ui = get_ui_entrypoint();
+  let mut current_ui = get_ui_entrypoint(boot_options().get("scene").unwrap_or("start"));
 
-  let mut last_time = time_manager().uptime();
+  if let Some(fps) = boot_options().get_u32("fps") {
+    scheduler::set_target_fps(fps);
+  }
+  let tick_rate = match boot_options().get_u32("tick_rate") {
+    Some(0) | None => scheduler::DEFAULT_TICK_RATE,
+    Some(tick_rate) => tick_rate,
+  };
+  info!("Target FPS: {}, tick rate: {} Hz", scheduler::target_fps(), tick_rate);
 
-  loop {
-    let mut dt = time_manager().uptime() - last_time;
-    let diff = TARGET_DT - dt.as_secs_f32();
-    if diff > 0.0 {
-      time_manager().spin_for(Duration::from_secs_f32(diff));
-      dt = time_manager().uptime() - last_time;
-    }
+  let stats_interval = boot_options()
+    .get_u32("frame_stats")
+    .unwrap_or(DEFAULT_FRAME_STATS_INTERVAL_SECS);
+  if stats_interval > 0 {
+    stats::start_periodic_summary(Duration::from_secs(stats_interval as u64));
+  }
+  if let Some(secs) = boot_options().get_u32("profile").filter(|secs| *secs > 0) {
+    profile::start_periodic_report(Duration::from_secs(secs as u64));
+  }
+  if let Some(secs) = boot_options().get_u32("heap_dump").filter(|secs| *secs > 0) {
+    bsp::alloc::start_periodic_dump(Duration::from_secs(secs as u64));
+  }
 
-    last_time = time_manager().uptime();
-    current_ui.on_tick(dt);
-    if current_ui.should_draw() {
-      current_ui.draw(&mut fb);
-    }
+  FrameScheduler::new(tick_rate).run(&mut current_ui, &mut fb)
+}
+
+/// Entry of cores 1-3. Brings the core to the same state as the boot core,
+/// then waits for work.
+unsafe fn kernel_secondary_main(core_id: usize) -> ! {
+  exception::handling_init();
 
-    fb.update_fb();
+  // Translation tables have already been populated by the boot core
+  if let Err(err) = mmu().enable_mmu_and_caching() {
+    panic!("MMU on core {}: {:?}", core_id, err);
   }
+
+  bsp::exception::asynchronous::init_secondary_core();
+  time::init_secondary_core();
+  pmu::init();
+  cpu::local_irq_unmask();
+
+  cpu::smp::secondary_core_loop(core_id)
 }
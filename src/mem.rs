@@ -1,34 +1,157 @@
 use core::cell::UnsafeCell;
+use core::ops::RangeInclusive;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 use crate::bsp::alloc::ALLOCATOR;
-use crate::info;
+use crate::bsp::devicetree;
+use crate::bsp::mailbox::memory_split;
+use crate::bsp::memory::map::PAGE_SIZE;
+use crate::{info, warn};
+
+pub mod dma;
+pub mod mmu;
+pub mod page_alloc;
 
 extern "Rust" {
-  static __bss_end_exclusive: UnsafeCell<()>;
+  static __kernel_end_exclusive: UnsafeCell<()>;
 }
 
 // Memory Locations
 
+/// The heap gets this much, the page frame allocator the rest of ARM memory.
+/// Also the heap size when the firmware can't tell how much memory there is.
+const HEAP_SIZE: usize = 1024 * 1024 * 128; // 128 MiB
+
+/// End of the heap, set by `init_heap`.
+static HEAP_END: AtomicUsize = AtomicUsize::new(0);
+
+fn kernel_end() -> usize {
+  unsafe { __kernel_end_exclusive.get() as usize }
+}
+
+/// The heap follows the DMA region.
 fn heap_start() -> usize {
-  unsafe { __bss_end_exclusive.get() as usize }
+  dma::dma_range_inclusive().end() + 1
+}
+
+/// Memory the kernel claims for itself: stacks, image and the DMA region.
+/// The first page belongs to the firmware.
+pub fn kernel_range_inclusive() -> RangeInclusive<usize> {
+  PAGE_SIZE..=heap_start() - 1
 }
 
-const fn heap_size() -> usize {
-  1024 * 1024 * 128 // 128 MiB
+/// The end of the ARM memory the kernel may use, as reported by the firmware.
+fn usable_memory_end(heap_start: usize) -> Result<usize, &'static str> {
+  let (arm, vc) = memory_split()?;
+  info!("ARM memory {:#010x} - {:#010x}", arm.start(), arm.end());
+  info!("VC memory  {:#010x} - {:#010x}", vc.start(), vc.end());
+
+  // The VideoCore's share normally follows the ARM one. Never hand it out.
+  if vc.contains(&heap_start) {
+    panic!("The kernel image reaches into VC memory");
+  }
+  let arm_end = arm.end() + 1;
+  if *vc.start() > heap_start {
+    Ok(arm_end.min(*vc.start()))
+  } else {
+    Ok(arm_end)
+  }
 }
 
-fn heap_end() -> usize {
-  heap_start() + heap_size() + 1
+/// Memory that neither the heap nor the page frames may hand out, with what is
+/// in it.
+fn reserved_ranges() -> impl Iterator<Item = (RangeInclusive<usize>, &'static str)> {
+  devicetree::device_tree_range_inclusive()
+    .map(|range| (range, "device tree"))
+    .into_iter()
+    .chain(devicetree::mem_reservations().map(|range| (range, "memreserve entry")))
 }
 
-pub fn mailbox_heap_location() -> usize {
-  heap_end() + (16 - (heap_end() % 16))
+/// The memory `init_heap` gave to the heap.
+pub fn heap_range_inclusive() -> RangeInclusive<usize> {
+  heap_start()..=HEAP_END.load(Ordering::Relaxed) - 1
 }
 
+/// Split the ARM memory the kernel doesn't use between the heap and the page
+/// frame allocator, and log the memory map. The VideoCore's memory, which
+/// holds the framebuffer, and the reserved ranges stay clear.
 pub fn init_heap() {
   let heap_start = heap_start();
-  let heap_size = heap_size();
-  info!("Allocating Heap {:#01x}-{:#01x}", heap_start, heap_start + heap_size);
+  let memory_end = match usable_memory_end(heap_start) {
+    Ok(memory_end) => memory_end,
+    Err(err) => {
+      warn!(
+        "Failed to query the memory split ({}), assuming {} MiB",
+        err,
+        HEAP_SIZE >> 20
+      );
+      heap_start + HEAP_SIZE
+    },
+  };
+
+  // The heap is a single block, so it ends at the first reserved range in its
+  // way, and there is none if one reaches into its start. The page frames
+  // take the rest and skip the reserved pages.
+  let heap_end = reserved_ranges()
+    .filter(|(range, _)| *range.end() >= heap_start)
+    .map(|(range, _)| *range.start())
+    .fold(memory_end.min(heap_start + HEAP_SIZE), usize::min)
+    & !(PAGE_SIZE - 1);
+  if heap_end <= heap_start {
+    panic!("No memory left for the heap");
+  }
+  // The kernel can't get out of the way. Its range leaves out the first page,
+  // which the firmware normally reserves.
+  let kernel_range = kernel_range_inclusive();
+  for (range, what) in reserved_ranges() {
+    if range.start() <= kernel_range.end() && kernel_range.start() <= range.end() {
+      warn!(
+        "Reserved {} at {:#x} - {:#x} overlaps the kernel",
+        what,
+        range.start(),
+        range.end()
+      );
+    }
+  }
+  HEAP_END.store(heap_end, Ordering::Relaxed);
+
+  let heap_size = heap_end - heap_start;
   ALLOCATOR.init(heap_start, heap_size);
-  info!("Mailbox Heap Location {:#01x}", mailbox_heap_location())
+
+  // Needs the heap for its bitmap.
+  let frames_end = memory_end & !(PAGE_SIZE - 1);
+  page_alloc::init(heap_end..frames_end);
+  for (range, _) in reserved_ranges() {
+    page_alloc::reserve(range);
+  }
+
+  info!("Memory map:");
+  info!("      {:#010x} - {:#010x} Kernel image and stacks", 0, kernel_end() - 1);
+  info!(
+    "      {:#010x} - {:#010x} DMA region",
+    dma::dma_range_inclusive().start(),
+    dma::dma_range_inclusive().end()
+  );
+  info!(
+    "      {:#010x} - {:#010x} Heap, {} MiB",
+    heap_start,
+    heap_end - 1,
+    heap_size >> 20
+  );
+  if frames_end > heap_end {
+    info!(
+      "      {:#010x} - {:#010x} Page frames, {} MiB",
+      heap_end,
+      frames_end - 1,
+      (frames_end - heap_end) >> 20
+    );
+  }
+  for (range, what) in reserved_ranges() {
+    info!(
+      "      {:#010x} - {:#010x} Reserved, {}",
+      range.start(),
+      range.end(),
+      what
+    );
+  }
 }
@@ -0,0 +1,98 @@
+//! DMA-coherent memory.
+//!
+//! Buffers shared with the VideoCore and other bus masters come from a region
+//! right after the kernel image. It is mapped non-cacheable, so neither side
+//! needs cache maintenance, only a `cpu::memory_barrier` between writing a
+//! buffer and handing it to the device. Devices address the buffers through
+//! the VideoCore's uncached alias of ARM memory.
+
+use core::alloc::Layout;
+use core::ops::RangeInclusive;
+use core::ptr::{self, NonNull};
+
+use linked_list_allocator::Heap;
+
+use crate::bsp::memory::map::{DMA_BUS_ALIAS, PAGE_SIZE};
+use crate::cpu::SpinLock;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Mailbox messages take a few hundred bytes, device rings a few pages.
+const DMA_REGION_SIZE: usize = 16 * PAGE_SIZE;
+
+/// The mailbox keeps the channel in the low 4 bits of addresses.
+const MIN_ALIGN: usize = 16;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A zeroed, DMA-coherent buffer. Freed when dropped.
+pub struct DmaBuffer {
+  ptr: NonNull<u8>,
+  layout: Layout,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static DMA_HEAP: SpinLock<Heap> = SpinLock::new("DMA heap", Heap::empty());
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// The DMA region, i.e. the pages after the kernel image.
+pub fn dma_range_inclusive() -> RangeInclusive<usize> {
+  let start = super::kernel_end();
+  start..=start + DMA_REGION_SIZE - 1
+}
+
+/// Hand the DMA region to the allocator. Must run before the first
+/// `DmaBuffer`, which the mailbox needs.
+pub fn init() {
+  let range = dma_range_inclusive();
+  unsafe { DMA_HEAP.lock().init(*range.start(), DMA_REGION_SIZE) }
+}
+
+// The buffer is owned memory, like a `Box`.
+unsafe impl Send for DmaBuffer {}
+
+impl DmaBuffer {
+  /// Allocate `size` bytes, aligned to `align` and to 16 bytes at least.
+  pub fn new(size: usize, align: usize) -> Result<Self, &'static str> {
+    let layout = Layout::from_size_align(size.max(1), align.max(MIN_ALIGN)).map_err(|_| "Bad DMA buffer layout")?;
+    let ptr = DMA_HEAP
+      .lock()
+      .allocate_first_fit(layout)
+      .map_err(|_| "Out of DMA memory")?;
+
+    unsafe { ptr::write_bytes(ptr.as_ptr(), 0, layout.size()) };
+    Ok(Self { ptr, layout })
+  }
+
+  /// The size in bytes.
+  #[allow(dead_code)]
+  pub fn size(&self) -> usize {
+    self.layout.size()
+  }
+
+  /// Where the CPU accesses the buffer.
+  pub fn as_mut_ptr(&self) -> *mut u8 {
+    self.ptr.as_ptr()
+  }
+
+  /// Where devices access the buffer.
+  pub fn bus_address(&self) -> u32 {
+    self.ptr.as_ptr() as u32 | DMA_BUS_ALIAS
+  }
+}
+
+impl Drop for DmaBuffer {
+  fn drop(&mut self) {
+    unsafe { DMA_HEAP.lock().deallocate(self.ptr, self.layout) }
+  }
+}
@@ -0,0 +1,220 @@
+//! Memory Management Unit.
+//!
+//! The kernel runs identity mapped. The BSP describes which parts of the
+//! address space need special attributes, the arch code turns that description
+//! into translation tables and switches on the MMU and caches.
+
+#[cfg(target_arch = "aarch64")]
+#[path = "../arch/aarch64/mem/mmu.rs"]
+mod arch_mmu;
+
+use core::fmt;
+use core::ops::RangeInclusive;
+
+//--------------------------------------------------------------------------------------------------
+// Architectural Public Reexports
+//--------------------------------------------------------------------------------------------------
+pub use arch_mmu::mmu;
+
+use crate::info;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// MMU enable errors variants.
+#[derive(Debug)]
+pub enum MMUEnableError {
+  AlreadyEnabled,
+  Other(&'static str),
+}
+
+/// Memory Management interfaces.
+pub mod interface {
+  use super::*;
+
+  /// MMU functions.
+  pub trait Mmu {
+    /// Populate the translation tables from the BSP layout. Only done once, by
+    /// the boot core.
+    ///
+    /// # Safety
+    ///
+    /// - Must not be called while any core has the MMU enabled.
+    unsafe fn populate_translation_tables(&self) -> Result<(), &'static str>;
+
+    /// Turn on the MMU and data/instruction caching on the executing core,
+    /// using the already populated translation tables.
+    ///
+    /// # Safety
+    ///
+    /// - Changes the HW's global state.
+    unsafe fn enable_mmu_and_caching(&self) -> Result<(), MMUEnableError>;
+
+    /// Returns true if the MMU is enabled, false otherwise.
+    fn is_enabled(&self) -> bool;
+
+    /// Change the attributes of an already mapped range. The range is widened
+    /// to page granularity.
+    ///
+    /// # Safety
+    ///
+    /// - Nobody may access the range while its attributes are changed.
+    unsafe fn remap(&self, range: RangeInclusive<usize>, attributes: AttributeFields) -> Result<(), &'static str>;
+  }
+}
+
+/// Architecture agnostic memory attributes.
+#[allow(dead_code)]
+#[derive(Copy, Clone)]
+pub enum MemAttributes {
+  /// Normal memory, write-back cacheable.
+  CacheableDRAM,
+  /// Normal memory, non-cacheable. Writes may be gathered and buffered, which
+  /// is what scan-out buffers want.
+  WriteCombining,
+  /// Device-nGnRE, for MMIO.
+  Device,
+}
+
+/// Architecture agnostic access permissions.
+#[allow(dead_code)]
+#[derive(Copy, Clone)]
+pub enum AccessPermissions {
+  ReadOnly,
+  ReadWrite,
+}
+
+/// Collection of memory attributes.
+#[derive(Copy, Clone)]
+pub struct AttributeFields {
+  pub mem_attributes: MemAttributes,
+  pub acc_perms: AccessPermissions,
+  pub execute_never: bool,
+}
+
+/// A range with special attributes in the kernel's address space.
+pub struct TranslationDescriptor {
+  pub name: &'static str,
+  pub range: fn() -> RangeInclusive<usize>,
+  pub attribute_fields: AttributeFields,
+}
+
+/// An unmapped range below a stack. Running into it faults, and the exception
+/// handler reports the fault as a stack overflow.
+pub struct StackGuard {
+  pub name: &'static str,
+  pub range: fn() -> RangeInclusive<usize>,
+}
+
+/// Type for expressing the kernel's address space layout.
+pub struct KernelVirtualLayout<const NUM_SPECIAL_RANGES: usize> {
+  /// The last (inclusive) address of the address space.
+  max_virt_addr_inclusive: usize,
+
+  /// Attributes of everything not covered by `inner`.
+  default: AttributeFields,
+
+  /// Array of descriptors for non-standard (normal cacheable DRAM) memory
+  /// regions.
+  inner: [TranslationDescriptor; NUM_SPECIAL_RANGES],
+
+  /// Ranges that are left unmapped. They take precedence over `inner`.
+  stack_guards: &'static [StackGuard],
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Human readable output of AttributeFields
+impl fmt::Display for AttributeFields {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let attr = match self.mem_attributes {
+      MemAttributes::CacheableDRAM => "C",
+      MemAttributes::WriteCombining => "WC",
+      MemAttributes::Device => "Dev",
+    };
+
+    let acc_p = match self.acc_perms {
+      AccessPermissions::ReadOnly => "RO",
+      AccessPermissions::ReadWrite => "RW",
+    };
+
+    let xn = if self.execute_never { "PXN" } else { "PX" };
+
+    write!(f, "{: <3} {} {: <3}", attr, acc_p, xn)
+  }
+}
+
+impl<const NUM_SPECIAL_RANGES: usize> KernelVirtualLayout<{ NUM_SPECIAL_RANGES }> {
+  /// Create a new instance.
+  pub const fn new(
+    max: usize,
+    default: AttributeFields,
+    layout: [TranslationDescriptor; NUM_SPECIAL_RANGES],
+    stack_guards: &'static [StackGuard],
+  ) -> Self {
+    Self {
+      max_virt_addr_inclusive: max,
+      default,
+      inner: layout,
+      stack_guards,
+    }
+  }
+
+  /// The last (inclusive) address of the address space.
+  pub const fn max_virt_addr_inclusive(&self) -> usize {
+    self.max_virt_addr_inclusive
+  }
+
+  /// For an address, search the layout and return the attributes of the range
+  /// it belongs to. Addresses not covered by any range get the default
+  /// attributes.
+  pub fn attributes_for(&self, addr: usize) -> Result<AttributeFields, &'static str> {
+    if addr > self.max_virt_addr_inclusive {
+      return Err("Address out of range");
+    }
+
+    Ok(
+      self
+        .inner
+        .iter()
+        .find(|i| (i.range)().contains(&addr))
+        .map_or(self.default, |i| i.attribute_fields),
+    )
+  }
+
+  /// The stack guard an address falls into, if any.
+  pub fn stack_guard_for(&self, addr: usize) -> Option<&StackGuard> {
+    self.stack_guards.iter().find(|g| (g.range)().contains(&addr))
+  }
+
+  /// Print the memory layout.
+  pub fn print_layout(&self) {
+    info!("MMU special regions:");
+    for i in self.inner.iter() {
+      let range = (i.range)();
+      info!(
+        "      {:#010x} - {:#010x} | {} | {}",
+        range.start(),
+        range.end(),
+        i.attribute_fields,
+        i.name
+      );
+    }
+    for g in self.stack_guards.iter() {
+      let range = (g.range)();
+      info!(
+        "      {:#010x} - {:#010x} | unmapped    | {}",
+        range.start(),
+        range.end(),
+        g.name
+      );
+    }
+    info!(
+      "      everything else up to {:#010x} | {}",
+      self.max_virt_addr_inclusive, self.default
+    );
+  }
+}
@@ -0,0 +1,68 @@
+//! Physical page frame allocator.
+//!
+//! Hands out page-granular, physically contiguous memory for large buffers,
+//! which would fragment the heap. The bookkeeping is in `page_frames`.
+//! The pages are mapped like all normal DRAM, i.e. cacheable. Buffers for
+//! devices need remapping, see `alloc_contiguous`.
+
+use core::ops::{Range, RangeInclusive};
+
+use game_console::page_frames::PageFrames;
+
+use crate::bsp::memory::map::PAGE_SIZE;
+use crate::cpu::SpinLock;
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static PAGE_FRAMES: SpinLock<PageFrames> = SpinLock::new("page frames", PageFrames::empty(PAGE_SIZE));
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Manage the pages in `range`. Both ends must be page aligned. Needs the
+/// heap.
+pub fn init(range: Range<usize>) {
+  PAGE_FRAMES.lock().init(range)
+}
+
+/// Keep the pages `range` touches from being handed out.
+pub fn reserve(range: RangeInclusive<usize>) {
+  PAGE_FRAMES.lock().reserve(range)
+}
+
+/// Allocate `count` contiguous pages, the first one aligned to `align` bytes.
+/// `align` must be a power of two, anything up to `PAGE_SIZE` is always
+/// satisfied. Returns the address of the first page.
+#[allow(dead_code)]
+pub fn alloc_pages(count: usize, align: usize) -> Option<usize> {
+  PAGE_FRAMES.lock().alloc(count, align)
+}
+
+/// Return pages from `alloc_pages`. Panics if they aren't allocated.
+#[allow(dead_code)]
+pub fn free_pages(address: usize, count: usize) {
+  PAGE_FRAMES.lock().free(address, count)
+}
+
+/// Allocate physically contiguous memory for at least `size` bytes, e.g. a
+/// DMA buffer that doesn't fit the DMA region. The pages are cacheable, map
+/// them with `mmu().remap` before handing them to a device.
+#[allow(dead_code)]
+pub fn alloc_contiguous(size: usize, align: usize) -> Option<RangeInclusive<usize>> {
+  PAGE_FRAMES.lock().alloc_contiguous(size, align)
+}
+
+/// Return memory from `alloc_contiguous`.
+#[allow(dead_code)]
+pub fn free_contiguous(range: RangeInclusive<usize>) {
+  PAGE_FRAMES.lock().free_contiguous(range)
+}
+
+/// The number of free pages.
+#[allow(dead_code)]
+pub fn free_page_count() -> usize {
+  PAGE_FRAMES.lock().free_count()
+}
@@ -0,0 +1,283 @@
+//! The bitmap behind the page frame allocator.
+//!
+//! One bit per page tracks what is in use. It holds no lock and knows nothing
+//! about the memory it manages, so it can be tested on the host.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::{Range, RangeInclusive};
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+pub struct PageFrames {
+  page_size: usize,
+  /// Address of the first page.
+  start: usize,
+  pages: usize,
+  free: usize,
+  /// One bit per page, set while it is allocated.
+  used: Vec<u64>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl PageFrames {
+  fn is_used(&self, page: usize) -> bool {
+    self.used[page / 64] & (1 << (page % 64)) != 0
+  }
+
+  fn set_used(&mut self, pages: Range<usize>, used: bool) {
+    for page in pages {
+      if used {
+        self.used[page / 64] |= 1 << (page % 64);
+      } else {
+        self.used[page / 64] &= !(1 << (page % 64));
+      }
+    }
+  }
+
+  fn address(&self, page: usize) -> usize {
+    self.start + page * self.page_size
+  }
+
+  /// The first page from `page` on whose address is a multiple of `align`.
+  fn next_aligned(&self, page: usize, align: usize) -> usize {
+    let address = (self.address(page) + align - 1) & !(align - 1);
+    (address - self.start) / self.page_size
+  }
+
+  /// First fit.
+  fn find_free(&self, count: usize, align: usize) -> Option<usize> {
+    let mut first = self.next_aligned(0, align);
+    while first + count <= self.pages {
+      match (first..first + count).rev().find(|page| self.is_used(*page)) {
+        Some(used) => first = self.next_aligned(used + 1, align),
+        None => return Some(first),
+      }
+    }
+    None
+  }
+
+  fn pages_for(&self, size: usize) -> usize {
+    (size + self.page_size - 1) / self.page_size
+  }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl PageFrames {
+  /// No pages of `page_size` bytes, until `init`. `page_size` must be a power
+  /// of two.
+  pub const fn empty(page_size: usize) -> Self {
+    Self {
+      page_size,
+      start: 0,
+      pages: 0,
+      free: 0,
+      used: Vec::new(),
+    }
+  }
+
+  /// Manage the pages in `range`, all free. Both ends must be page aligned.
+  pub fn init(&mut self, range: Range<usize>) {
+    let pages = range.end.saturating_sub(range.start) / self.page_size;
+
+    self.start = range.start;
+    self.pages = pages;
+    self.free = pages;
+    self.used = vec![0; (pages + 63) / 64];
+  }
+
+  /// Allocate `count` contiguous pages, the first one aligned to `align` bytes.
+  /// `align` must be a power of two, anything up to the page size is always
+  /// satisfied. Returns the address of the first page.
+  pub fn alloc(&mut self, count: usize, align: usize) -> Option<usize> {
+    if count == 0 || !align.is_power_of_two() {
+      return None;
+    }
+
+    let first = self.find_free(count, align.max(self.page_size))?;
+    self.set_used(first..first + count, true);
+    self.free -= count;
+    Some(self.address(first))
+  }
+
+  /// Return pages from `alloc`. Panics if they aren't allocated.
+  pub fn free(&mut self, address: usize, count: usize) {
+    let first = address.wrapping_sub(self.start) / self.page_size;
+    if address < self.start || address % self.page_size != 0 || first + count > self.pages {
+      panic!("Freeing pages {:#x} (+{}) outside of the page frames", address, count);
+    }
+    if let Some(page) = (first..first + count).find(|page| !self.is_used(*page)) {
+      panic!("Freeing page {:#x}, which is not allocated", self.address(page));
+    }
+
+    self.set_used(first..first + count, false);
+    self.free += count;
+  }
+
+  /// Allocate whole pages for at least `size` bytes, aligned like `alloc`.
+  pub fn alloc_contiguous(&mut self, size: usize, align: usize) -> Option<RangeInclusive<usize>> {
+    let count = self.pages_for(size.max(1));
+    let start = self.alloc(count, align)?;
+    Some(start..=start + count * self.page_size - 1)
+  }
+
+  /// Return memory from `alloc_contiguous`.
+  pub fn free_contiguous(&mut self, range: RangeInclusive<usize>) {
+    let count = self.pages_for(range.end() - range.start() + 1);
+    self.free(*range.start(), count)
+  }
+
+  /// Never hand out the pages `range` touches, e.g. memory the firmware left
+  /// data in. Parts outside the page frames are ignored.
+  pub fn reserve(&mut self, range: RangeInclusive<usize>) {
+    let end = self.address(self.pages);
+    if *range.end() < self.start || *range.start() >= end {
+      return;
+    }
+
+    let first = (range.start().max(&self.start) - self.start) / self.page_size;
+    let last = (range.end().min(&(end - 1)) - self.start) / self.page_size;
+    let newly_used = (first..=last).filter(|page| !self.is_used(*page)).count();
+    self.set_used(first..last + 1, true);
+    self.free -= newly_used;
+  }
+
+  /// The number of free pages.
+  pub fn free_count(&self) -> usize {
+    self.free
+  }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const PAGE_SIZE: usize = 0x1000;
+  const START: usize = 0x10_0000;
+
+  fn frames(pages: usize) -> PageFrames {
+    let mut frames = PageFrames::empty(PAGE_SIZE);
+    frames.init(START..START + pages * PAGE_SIZE);
+    frames
+  }
+
+  #[test]
+  fn first_fit() {
+    let mut frames = frames(8);
+    assert_eq!(frames.alloc(2, 1), Some(START));
+    assert_eq!(frames.alloc(1, PAGE_SIZE), Some(START + 2 * PAGE_SIZE));
+    assert_eq!(frames.free_count(), 5);
+  }
+
+  #[test]
+  fn alignment() {
+    let mut frames = frames(32);
+    assert_eq!(frames.alloc(1, 1), Some(START));
+    // The next 64 KiB boundary, skipping the pages before it.
+    assert_eq!(frames.alloc(2, 0x1_0000), Some(START + 0x1_0000));
+    assert_eq!(frames.alloc(1, 1), Some(START + PAGE_SIZE));
+    // The only 128 KiB boundary in range is taken.
+    assert_eq!(frames.alloc(1, 0x2_0000), None);
+    assert_eq!(frames.alloc(1, 3), None);
+    assert_eq!(frames.free_count(), 28);
+  }
+
+  #[test]
+  fn contiguous() {
+    let mut frames = frames(32);
+    assert_eq!(frames.alloc_contiguous(0, 1), Some(START..=START + PAGE_SIZE - 1));
+    assert_eq!(
+      frames.alloc_contiguous(PAGE_SIZE + 1, 0x1_0000),
+      Some(START + 0x1_0000..=START + 0x1_0000 + 2 * PAGE_SIZE - 1)
+    );
+    assert_eq!(frames.free_count(), 29);
+
+    frames.free_contiguous(START + 0x1_0000..=START + 0x1_0000 + 2 * PAGE_SIZE - 1);
+    assert_eq!(frames.free_count(), 31);
+    assert_eq!(frames.alloc_contiguous(32 * PAGE_SIZE, 1), None);
+  }
+
+  #[test]
+  fn exhaustion() {
+    let mut frames = frames(4);
+    assert_eq!(frames.alloc(5, 1), None);
+    assert_eq!(frames.alloc(0, 1), None);
+    assert_eq!(frames.alloc(3, 1), Some(START));
+    assert_eq!(frames.alloc(2, 1), None);
+    assert_eq!(frames.alloc(1, 1), Some(START + 3 * PAGE_SIZE));
+    assert_eq!(frames.alloc(1, 1), None);
+    assert_eq!(frames.free_count(), 0);
+  }
+
+  #[test]
+  fn reuse_after_free() {
+    let mut frames = frames(4);
+    let a = frames.alloc(2, 1).unwrap();
+    let b = frames.alloc(2, 1).unwrap();
+    assert_eq!(frames.free_count(), 0);
+
+    frames.free(a, 2);
+    assert_eq!(frames.free_count(), 2);
+    assert_eq!(frames.alloc(2, 1), Some(a));
+
+    frames.free(b, 2);
+    frames.free(a, 2);
+    assert_eq!(frames.free_count(), 4);
+    assert_eq!(frames.alloc(4, 1), Some(START));
+  }
+
+  #[test]
+  fn gap_too_small() {
+    let mut frames = frames(6);
+    let a = frames.alloc(2, 1).unwrap();
+    frames.alloc(1, 1).unwrap();
+    frames.free(a, 2);
+    // The two free pages in front don't fit three.
+    assert_eq!(frames.alloc(3, 1), Some(START + 3 * PAGE_SIZE));
+  }
+
+  #[test]
+  fn reserve() {
+    let mut frames = frames(8);
+    frames.alloc(1, 1).unwrap();
+
+    // Touches pages 0 to 2, the first one already allocated.
+    frames.reserve(START + 0x800..=START + 2 * PAGE_SIZE);
+    assert_eq!(frames.free_count(), 5);
+    // Partly outside, and entirely outside.
+    frames.reserve(START + 7 * PAGE_SIZE..=START + 9 * PAGE_SIZE);
+    frames.reserve(0..=START - 1);
+    assert_eq!(frames.free_count(), 4);
+
+    assert_eq!(frames.alloc(4, 1), Some(START + 3 * PAGE_SIZE));
+    assert_eq!(frames.alloc(1, 1), None);
+  }
+
+  #[test]
+  #[should_panic(expected = "not allocated")]
+  fn double_free() {
+    let mut frames = frames(4);
+    let a = frames.alloc(1, 1).unwrap();
+    frames.free(a, 1);
+    frames.free(a, 1);
+  }
+
+  #[test]
+  #[should_panic(expected = "outside of the page frames")]
+  fn free_outside() {
+    let mut frames = frames(4);
+    frames.free(START + 4 * PAGE_SIZE, 1);
+  }
+}
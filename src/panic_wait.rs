@@ -1,6 +1,22 @@
 use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU32, Ordering};
+use core::time::Duration;
 
-use crate::{cpu, panic_println};
+use crate::time::interface::TimeManager;
+use crate::time::time_manager;
+use crate::{bsp, cpu, panic_println};
+
+/// Marks that panics wait forever.
+const NEVER: u32 = u32::MAX;
+
+/// Seconds between a panic and the reboot, or `NEVER`.
+static REBOOT_DELAY_SECS: AtomicU32 = AtomicU32::new(NEVER);
+
+/// Reboot `secs` seconds after a panic instead of waiting forever. Set at boot,
+/// as the panic handler can't take any locks.
+pub fn set_reboot_after_panic(secs: Option<u32>) {
+  REBOOT_DELAY_SECS.store(secs.unwrap_or(NEVER), Ordering::Relaxed);
+}
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
@@ -10,5 +26,12 @@ fn panic(info: &PanicInfo) -> ! {
     panic_println!("\nKernel panic!");
   }
 
-  cpu::wait_forever()
+  match REBOOT_DELAY_SECS.load(Ordering::Relaxed) {
+    NEVER => cpu::wait_forever(),
+    secs => {
+      panic_println!("Rebooting in {} s", secs);
+      time_manager().spin_for(Duration::from_secs(secs as u64));
+      bsp::power::reboot()
+    },
+  }
 }
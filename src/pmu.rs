@@ -0,0 +1,65 @@
+//! Performance monitors.
+//!
+//! Each core counts its own cycles and a few hardware events. Only differences
+//! between two samples are meaningful.
+
+#[cfg(target_arch = "aarch64")]
+#[path = "arch/aarch64/pmu.rs"]
+mod arch_pmu;
+
+use core::ops::AddAssign;
+
+//--------------------------------------------------------------------------------------------------
+// Architectural Public Reexports
+//--------------------------------------------------------------------------------------------------
+pub use arch_pmu::{init, read};
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A sample of the executing core's counters, or the difference of two.
+#[derive(Copy, Clone, Default)]
+pub struct Counters {
+  pub cycles: u64,
+  pub instructions: u64,
+  /// Level 1 data cache misses.
+  pub l1d_refills: u64,
+  /// Level 2 cache misses.
+  pub l2d_refills: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl Counters {
+  pub const ZERO: Self = Self {
+    cycles: 0,
+    instructions: 0,
+    l1d_refills: 0,
+    l2d_refills: 0,
+  };
+
+  /// The counts between the sample `earlier` and this one. The event
+  /// counters are 32 bits wide and wrap around.
+  pub fn since(&self, earlier: &Counters) -> Counters {
+    let events = |now: u64, earlier: u64| (now as u32).wrapping_sub(earlier as u32) as u64;
+
+    Counters {
+      cycles: self.cycles.wrapping_sub(earlier.cycles),
+      instructions: events(self.instructions, earlier.instructions),
+      l1d_refills: events(self.l1d_refills, earlier.l1d_refills),
+      l2d_refills: events(self.l2d_refills, earlier.l2d_refills),
+    }
+  }
+}
+
+impl AddAssign for Counters {
+  fn add_assign(&mut self, other: Counters) {
+    self.cycles += other.cycles;
+    self.instructions += other.instructions;
+    self.l1d_refills += other.l1d_refills;
+    self.l2d_refills += other.l2d_refills;
+  }
+}
@@ -0,0 +1,247 @@
+//! Profiling zones.
+//!
+//! `profile_zone!("name")` measures the rest of the enclosing scope with the
+//! PMU. Zones entered while another one is open become its children, and
+//! repeated entries of the same zone under the same parent add up. At the end
+//! of each frame, the tree of the executing core is kept as the last frame's
+//! profile and a new one starts.
+
+use core::time::Duration;
+
+use crate::bsp::cpu::NUM_CORES;
+use crate::cpu::{core_id, SpinLock};
+use crate::info;
+use crate::pmu::{self, Counters};
+use crate::time::timers::{self, TimerHandle};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Distinct zones per frame. Zones beyond are not recorded.
+const MAX_ZONES: usize = 32;
+
+/// Nesting levels. Deeper zones are not recorded.
+const MAX_DEPTH: usize = 8;
+
+#[derive(Copy, Clone)]
+struct ZoneStats {
+  name: &'static str,
+  parent: Option<usize>,
+  calls: u32,
+  total: Counters,
+}
+
+/// A zone tree, in the order the zones were first entered.
+#[derive(Copy, Clone)]
+struct ZoneTree {
+  zones: [ZoneStats; MAX_ZONES],
+  len: usize,
+}
+
+struct OpenZone {
+  index: usize,
+  start: Counters,
+}
+
+struct CoreProfile {
+  frame: ZoneTree,
+  last_frame: ZoneTree,
+  open: [Option<OpenZone>; MAX_DEPTH],
+  depth: usize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Closes its zone when dropped. Created by `profile_zone!`.
+pub struct ZoneGuard {
+  /// Whether the zone was recorded, i.e. there was room for it.
+  recorded: bool,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static PROFILES: [SpinLock<CoreProfile>; NUM_CORES] = [
+  SpinLock::new("profile", CoreProfile::new()),
+  SpinLock::new("profile", CoreProfile::new()),
+  SpinLock::new("profile", CoreProfile::new()),
+  SpinLock::new("profile", CoreProfile::new()),
+];
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl ZoneStats {
+  const EMPTY: Self = Self {
+    name: "",
+    parent: None,
+    calls: 0,
+    total: Counters::ZERO,
+  };
+}
+
+impl ZoneTree {
+  const fn new() -> Self {
+    Self {
+      zones: [ZoneStats::EMPTY; MAX_ZONES],
+      len: 0,
+    }
+  }
+
+  /// The index of the zone `name` under `parent`, added if it is new.
+  fn find_or_add(&mut self, name: &'static str, parent: Option<usize>) -> Option<usize> {
+    let zones = &self.zones[..self.len];
+    if let Some(index) = zones.iter().position(|zone| zone.parent == parent && zone.name == name) {
+      return Some(index);
+    }
+
+    if self.len == MAX_ZONES {
+      return None;
+    }
+
+    self.zones[self.len] = ZoneStats {
+      name,
+      parent,
+      ..ZoneStats::EMPTY
+    };
+    self.len += 1;
+    Some(self.len - 1)
+  }
+
+  fn print_children(&self, parent: Option<usize>, depth: usize) {
+    for (index, zone) in self.zones[..self.len].iter().enumerate() {
+      // Zones carried over from the frame before, not closed in this one.
+      if zone.parent != parent || zone.calls == 0 {
+        continue;
+      }
+
+      info!(
+        "      {:indent$}{:<width$} {:>11} cycles {:>10} instr {:>8} L1D miss {:>8} L2D miss {:>5}x",
+        "",
+        zone.name,
+        zone.total.cycles,
+        zone.total.instructions,
+        zone.total.l1d_refills,
+        zone.total.l2d_refills,
+        zone.calls,
+        indent = 2 * depth,
+        width = 24usize.saturating_sub(2 * depth),
+      );
+      self.print_children(Some(index), depth + 1);
+    }
+  }
+}
+
+impl CoreProfile {
+  const fn new() -> Self {
+    Self {
+      frame: ZoneTree::new(),
+      last_frame: ZoneTree::new(),
+      open: [None, None, None, None, None, None, None, None],
+      depth: 0,
+    }
+  }
+
+  fn enter(&mut self, name: &'static str) -> bool {
+    if self.depth == MAX_DEPTH {
+      return false;
+    }
+
+    let parent = match self.depth {
+      0 => None,
+      depth => self.open[depth - 1].as_ref().map(|zone| zone.index),
+    };
+
+    match self.frame.find_or_add(name, parent) {
+      Some(index) => {
+        self.open[self.depth] = Some(OpenZone {
+          index,
+          start: pmu::read(),
+        });
+        self.depth += 1;
+        true
+      },
+      None => false,
+    }
+  }
+
+  fn exit(&mut self, end: Counters) {
+    self.depth -= 1;
+
+    if let Some(zone) = self.open[self.depth].take() {
+      let stats = &mut self.frame.zones[zone.index];
+      stats.calls += 1;
+      stats.total += end.since(&zone.start);
+    }
+  }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl ZoneGuard {
+  pub fn enter(name: &'static str) -> Self {
+    Self {
+      recorded: PROFILES[core_id()].lock().enter(name),
+    }
+  }
+}
+
+impl Drop for ZoneGuard {
+  fn drop(&mut self) {
+    // Sample before taking the lock, so it isn't counted.
+    let end = pmu::read();
+
+    if self.recorded {
+      PROFILES[core_id()].lock().exit(end);
+    }
+  }
+}
+
+/// Profile the rest of the enclosing scope as the zone `name`.
+#[macro_export]
+macro_rules! profile_zone {
+  ($name:expr) => {
+    let _profile_zone = $crate::profile::ZoneGuard::enter($name);
+  };
+}
+
+/// Close the executing core's frame: keep its zones as the last frame's
+/// profile and start a new one. Zones still open carry over.
+pub fn end_frame() {
+  let mut profile = PROFILES[core_id()].lock();
+
+  profile.last_frame = profile.frame;
+
+  // Open zones keep their indices, so their ancestors stay, too.
+  let keep = profile.open[..profile.depth]
+    .iter()
+    .flatten()
+    .map(|zone| zone.index + 1)
+    .max()
+    .unwrap_or(0);
+  profile.frame.len = keep;
+  for zone in &mut profile.frame.zones[..keep] {
+    zone.calls = 0;
+    zone.total = Counters::ZERO;
+  }
+}
+
+/// Log the executing core's last frame profile, as a tree.
+pub fn print_last_frame() {
+  let last_frame = PROFILES[core_id()].lock().last_frame;
+
+  info!("Profile of the last frame on core {}:", core_id());
+  last_frame.print_children(None, 0);
+}
+
+/// Log the last frame profile every `interval`.
+pub fn start_periodic_report(interval: Duration) -> TimerHandle {
+  timers::start_periodic(interval, print_last_frame)
+}
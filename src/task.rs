@@ -0,0 +1,176 @@
+//! Cooperative tasks.
+//!
+//! A small executor for `Future`s. Tasks run on the boot core, interleaved with
+//! the frame loop, which polls the ready ones once per frame. A task that
+//! can't make progress returns `Pending` and gets polled again once its waker
+//! is woken, be it by an interrupt handler, a software timer or another task.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use core::time::Duration;
+
+use crate::cpu::SpinLock;
+use crate::time::timers::{self, TimerHandle};
+use crate::time::Instant;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+type Task = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Index into `TASKS`.
+type TaskId = usize;
+
+/// Tasks by ID. A slot is `None` when free, and while its task is polled.
+static TASKS: SpinLock<Vec<Option<Task>>> = SpinLock::new("tasks", Vec::new());
+
+/// Tasks to poll, in the order they were woken. Kept apart from `TASKS`, so
+/// waking is cheap and never waits for a poll to finish.
+static READY: SpinLock<Vec<TaskId>> = SpinLock::new("ready tasks", Vec::new());
+
+/// Wakers carry the ID of their task as data.
+static WAKER_VTABLE: RawWakerVTable = RawWakerVTable::new(clone_waker, wake, wake, drop_waker);
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Completes after its deadline. Created by `sleep`.
+pub struct Sleep {
+  deadline: Instant,
+  timer: Option<TimerHandle>,
+}
+
+/// Completes on the second poll. Created by `yield_now`.
+pub struct YieldNow {
+  yielded: bool,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn raw_waker(id: TaskId) -> RawWaker {
+  RawWaker::new(id as *const (), &WAKER_VTABLE)
+}
+
+unsafe fn clone_waker(data: *const ()) -> RawWaker {
+  raw_waker(data as TaskId)
+}
+
+unsafe fn wake(data: *const ()) {
+  READY.lock().push(data as TaskId);
+}
+
+unsafe fn drop_waker(_data: *const ()) {}
+
+fn waker(id: TaskId) -> Waker {
+  unsafe { Waker::from_raw(raw_waker(id)) }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Run `future` as a task. It is polled for the first time with the next
+/// `run_ready`.
+#[allow(dead_code)]
+pub fn spawn(future: impl Future<Output = ()> + Send + 'static) {
+  let task: Task = Box::pin(future);
+
+  let id = {
+    let mut tasks = TASKS.lock();
+    match tasks.iter().position(|slot| slot.is_none()) {
+      Some(id) => {
+        tasks[id] = Some(task);
+        id
+      },
+      None => {
+        tasks.push(Some(task));
+        tasks.len() - 1
+      },
+    }
+  };
+
+  READY.lock().push(id);
+}
+
+/// Poll each task that was woken. Tasks woken meanwhile wait for the next
+/// call, so one busy task can't stall the caller.
+pub fn run_ready() {
+  let ready = core::mem::take(&mut *READY.lock());
+
+  for id in ready {
+    // Take the task out, so it can spawn others while it is polled. Tasks woken
+    // twice, or already finished, have nothing to poll.
+    let mut task = match TASKS.lock().get_mut(id).and_then(Option::take) {
+      Some(task) => task,
+      None => continue,
+    };
+
+    let waker = waker(id);
+    if task.as_mut().poll(&mut Context::from_waker(&waker)).is_pending() {
+      TASKS.lock()[id] = Some(task);
+    }
+  }
+}
+
+/// Wait for `duration`, letting other tasks run.
+#[allow(dead_code)]
+pub fn sleep(duration: Duration) -> Sleep {
+  Sleep {
+    deadline: Instant::now() + duration,
+    timer: None,
+  }
+}
+
+/// Let the other ready tasks run before continuing.
+#[allow(dead_code)]
+pub fn yield_now() -> YieldNow {
+  YieldNow { yielded: false }
+}
+
+impl Future for Sleep {
+  type Output = ();
+
+  fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+    let now = Instant::now();
+    if now >= self.deadline {
+      return Poll::Ready(());
+    }
+
+    // A task only has one waker, one timer is enough.
+    if self.timer.is_none() {
+      let waker = cx.waker().clone();
+      self.timer = Some(timers::start_one_shot(self.deadline - now, move || waker.wake_by_ref()));
+    }
+
+    Poll::Pending
+  }
+}
+
+impl Drop for Sleep {
+  fn drop(&mut self) {
+    if let Some(timer) = self.timer {
+      timer.cancel();
+    }
+  }
+}
+
+impl Future for YieldNow {
+  type Output = ();
+
+  fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+    if self.yielded {
+      return Poll::Ready(());
+    }
+
+    self.yielded = true;
+    cx.waker().wake_by_ref();
+    Poll::Pending
+  }
+}
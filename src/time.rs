@@ -8,10 +8,29 @@
 #[path = "arch/aarch64/time.rs"]
 mod arch_time;
 
+mod instant;
+pub mod timers;
+
 //--------------------------------------------------------------------------------------------------
 // Architectural Public Reexports
 //--------------------------------------------------------------------------------------------------
-pub use arch_time::time_manager;
+pub use arch_time::{generic_timer, init, init_secondary_core};
+pub use instant::Instant;
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Return a reference to the time manager. That is the generic timer, or the
+/// BCM system timer with the `bsp_system_timer` feature. Both keep running,
+/// so either can be used to check the other for drift.
+pub fn time_manager() -> &'static impl interface::TimeManager {
+  #[cfg(feature = "bsp_system_timer")]
+  return crate::bsp::system_timer::system_timer();
+
+  #[cfg(not(feature = "bsp_system_timer"))]
+  return generic_timer();
+}
 
 //--------------------------------------------------------------------------------------------------
 // Public Definitions
@@ -21,6 +40,8 @@ pub use arch_time::time_manager;
 pub mod interface {
   use core::time::Duration;
 
+  use super::Instant;
+
   /// Time management functions.
   pub trait TimeManager {
     /// The timer's resolution.
@@ -33,5 +54,9 @@ pub mod interface {
 
     /// Spin for a given duration.
     fn spin_for(&self, duration: Duration);
+
+    /// Sleep until `deadline`. The core waits for the timer interrupt instead
+    /// of polling, other interrupts are served meanwhile.
+    fn sleep_until(&self, deadline: Instant);
   }
 }
@@ -0,0 +1,108 @@
+//! Points in time.
+
+use core::ops::{Add, AddAssign, Sub, SubAssign};
+use core::time::Duration;
+
+use super::interface::TimeManager;
+use super::time_manager;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A point on the monotonic uptime clock, like `std::time::Instant`.
+///
+/// Arithmetic through the operators panics on overflow, the `checked_*`
+/// methods return `None` instead. Differences of instants saturate at zero.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Instant {
+  since_power_on: Duration,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl Instant {
+  /// The current time.
+  pub fn now() -> Self {
+    Self {
+      since_power_on: time_manager().uptime(),
+    }
+  }
+
+  /// The time between power-on of the device and this instant.
+  pub fn since_power_on(&self) -> Duration {
+    self.since_power_on
+  }
+
+  /// The time from `earlier` to this instant, or zero if `earlier` is later.
+  pub fn duration_since(&self, earlier: Instant) -> Duration {
+    self.checked_duration_since(earlier).unwrap_or_default()
+  }
+
+  /// The time from `earlier` to this instant, or `None` if `earlier` is later.
+  pub fn checked_duration_since(&self, earlier: Instant) -> Option<Duration> {
+    self.since_power_on.checked_sub(earlier.since_power_on)
+  }
+
+  /// The time that passed since this instant.
+  pub fn elapsed(&self) -> Duration {
+    Instant::now().duration_since(*self)
+  }
+
+  pub fn checked_add(&self, duration: Duration) -> Option<Instant> {
+    self
+      .since_power_on
+      .checked_add(duration)
+      .map(|since_power_on| Self { since_power_on })
+  }
+
+  /// Returns `None` for instants before power-on, too.
+  pub fn checked_sub(&self, duration: Duration) -> Option<Instant> {
+    self
+      .since_power_on
+      .checked_sub(duration)
+      .map(|since_power_on| Self { since_power_on })
+  }
+}
+
+impl Add<Duration> for Instant {
+  type Output = Instant;
+
+  fn add(self, duration: Duration) -> Instant {
+    self
+      .checked_add(duration)
+      .expect("Overflow when adding duration to instant")
+  }
+}
+
+impl AddAssign<Duration> for Instant {
+  fn add_assign(&mut self, duration: Duration) {
+    *self = *self + duration;
+  }
+}
+
+impl Sub<Duration> for Instant {
+  type Output = Instant;
+
+  fn sub(self, duration: Duration) -> Instant {
+    self
+      .checked_sub(duration)
+      .expect("Overflow when subtracting duration from instant")
+  }
+}
+
+impl SubAssign<Duration> for Instant {
+  fn sub_assign(&mut self, duration: Duration) {
+    *self = *self - duration;
+  }
+}
+
+impl Sub<Instant> for Instant {
+  type Output = Duration;
+
+  fn sub(self, earlier: Instant) -> Duration {
+    self.duration_since(earlier)
+  }
+}
@@ -0,0 +1,115 @@
+//! Software timers.
+//!
+//! One-shot and periodic callbacks on top of the `TimeManager`. The queue is
+//! kept sorted by deadline, timers with the same deadline fire in the order
+//! they were started. Callbacks run from `run_expired` or `run_until`, i.e.
+//! in the main loop, not in interrupt context, so they may take locks, use
+//! the heap and start or cancel timers themselves.
+//!
+//! The queue itself is in `timer_queue`, which holds no locks and reads no
+//! clock, so it can be tested on the host.
+
+use alloc::boxed::Box;
+use core::time::Duration;
+
+use game_console::timer_queue::{Timer, TimerQueue};
+
+use super::interface::TimeManager;
+use super::{time_manager, Instant};
+use crate::cpu::SpinLock;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+type Callback = Box<dyn FnMut() + Send>;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Refers to a started timer. Dropping it leaves the timer running.
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub struct TimerHandle(u64);
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static TIMER_QUEUE: SpinLock<TimerQueue<Instant, Callback>> = SpinLock::new("timer queue", TimerQueue::new());
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn start(delay: Duration, period: Option<Duration>, callback: Callback) -> TimerHandle {
+  let deadline = Instant::now() + delay;
+  TimerHandle(TIMER_QUEUE.lock().start(deadline, period, callback))
+}
+
+/// Like `TimerQueue::pop_expired`, releasing the lock before returning.
+fn pop_expired(now: Instant) -> Option<Timer<Instant, Callback>> {
+  TIMER_QUEUE.lock().pop_expired(now)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl TimerHandle {
+  /// Stop the timer. Returns whether it was still pending, i.e. false for
+  /// one-shot timers that already fired.
+  ///
+  /// A periodic timer cancelled from its own callback is not rescheduled.
+  #[allow(dead_code)]
+  pub fn cancel(self) -> bool {
+    TIMER_QUEUE.lock().cancel(self.0)
+  }
+}
+
+/// Call `callback` once, `delay` from now.
+#[allow(dead_code)]
+pub fn start_one_shot(delay: Duration, callback: impl FnMut() + Send + 'static) -> TimerHandle {
+  start(delay, None, Box::new(callback))
+}
+
+/// Call `callback` every `period`, starting one period from now.
+///
+/// A callback that falls behind by more than a period skips the missed calls
+/// instead of running them back to back.
+///
+/// Panics if `period` is zero, which would keep the timer due forever.
+pub fn start_periodic(period: Duration, callback: impl FnMut() + Send + 'static) -> TimerHandle {
+  assert!(period > Duration::ZERO, "Periodic timer with a zero period");
+  start(period, Some(period), Box::new(callback))
+}
+
+/// The deadline of the next timer to fire.
+pub fn next_deadline() -> Option<Instant> {
+  TIMER_QUEUE.lock().next_deadline()
+}
+
+/// Run the callbacks of all timers that are due.
+pub fn run_expired() {
+  let now = Instant::now();
+
+  // The lock must not be held while the callback runs.
+  while let Some(mut timer) = pop_expired(now) {
+    (timer.callback)();
+    TIMER_QUEUE.lock().finish(timer, now);
+  }
+}
+
+/// Sleep until `deadline`, running the timers that become due meanwhile.
+pub fn run_until(deadline: Instant) {
+  loop {
+    run_expired();
+
+    if Instant::now() >= deadline {
+      break;
+    }
+
+    let wake_up = next_deadline().map_or(deadline, |next| next.min(deadline));
+    time_manager().sleep_until(wake_up);
+  }
+}
@@ -0,0 +1,221 @@
+//! The queue behind the software timers.
+//!
+//! Keeps timers sorted by deadline and tracks the one whose callback runs, but
+//! neither locks nor reads the clock: the caller passes in the current time.
+//! That keeps it testable on the host, with `Duration` standing in for
+//! `Instant`.
+
+use alloc::vec::Vec;
+use core::ops::Add;
+use core::time::Duration;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A started timer. `T` is the time type, `C` the callback.
+pub struct Timer<T, C> {
+  id: u64,
+  deadline: T,
+  /// `None` for one-shot timers.
+  period: Option<Duration>,
+  pub callback: C,
+}
+
+pub struct TimerQueue<T, C> {
+  next_id: u64,
+  /// Sorted by deadline, ties in start order.
+  timers: Vec<Timer<T, C>>,
+  /// The timer whose callback is running. It is not in `timers` meanwhile.
+  running: Option<u64>,
+  /// Whether the running timer was cancelled from within its callback.
+  running_cancelled: bool,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl<T: Copy + Ord + Add<Duration, Output = T>, C> TimerQueue<T, C> {
+  fn insert(&mut self, timer: Timer<T, C>) {
+    // After all timers with the same deadline.
+    let index = self.timers.partition_point(|queued| queued.deadline <= timer.deadline);
+    self.timers.insert(index, timer);
+  }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl<T, C> Timer<T, C> {
+  pub fn id(&self) -> u64 {
+    self.id
+  }
+}
+
+impl<T, C> TimerQueue<T, C> {
+  pub const fn new() -> Self {
+    Self {
+      next_id: 0,
+      timers: Vec::new(),
+      running: None,
+      running_cancelled: false,
+    }
+  }
+}
+
+impl<T: Copy + Ord + Add<Duration, Output = T>, C> TimerQueue<T, C> {
+  /// Queue a timer due at `deadline`, repeating every `period` if there is
+  /// one. Returns its id.
+  pub fn start(&mut self, deadline: T, period: Option<Duration>, callback: C) -> u64 {
+    let id = self.next_id;
+    self.next_id += 1;
+
+    self.insert(Timer {
+      id,
+      deadline,
+      period,
+      callback,
+    });
+    id
+  }
+
+  /// Stop the timer `id`. Returns whether it was still pending, i.e. false for
+  /// one-shot timers that already fired.
+  ///
+  /// A periodic timer cancelled while it runs is not rescheduled by `finish`.
+  pub fn cancel(&mut self, id: u64) -> bool {
+    if self.running == Some(id) {
+      let was_pending = !self.running_cancelled;
+      self.running_cancelled = true;
+      return was_pending;
+    }
+
+    match self.timers.iter().position(|timer| timer.id == id) {
+      Some(index) => {
+        self.timers.remove(index);
+        true
+      },
+      None => false,
+    }
+  }
+
+  /// Take out the first timer, if it is due at `now`. It counts as running
+  /// until it is handed back to `finish`.
+  pub fn pop_expired(&mut self, now: T) -> Option<Timer<T, C>> {
+    if self.timers.first()?.deadline > now {
+      return None;
+    }
+
+    let timer = self.timers.remove(0);
+    self.running = Some(timer.id);
+    self.running_cancelled = false;
+    Some(timer)
+  }
+
+  /// Hand back the timer from `pop_expired` after its callback ran. Periodic
+  /// timers that weren't cancelled are queued again one period later, or one
+  /// period from `now` if that is already past.
+  pub fn finish(&mut self, mut timer: Timer<T, C>, now: T) {
+    self.running = None;
+
+    if let (Some(period), false) = (timer.period, self.running_cancelled) {
+      timer.deadline = timer.deadline + period;
+      if timer.deadline <= now {
+        timer.deadline = now + period;
+      }
+      self.insert(timer);
+    }
+  }
+
+  /// The deadline of the next timer to fire.
+  pub fn next_deadline(&self) -> Option<T> {
+    self.timers.first().map(|timer| timer.deadline)
+  }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  type Queue = TimerQueue<Duration, &'static str>;
+
+  fn ms(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+  }
+
+  /// The callbacks of all timers due at `now`, running and finishing them.
+  fn run(queue: &mut Queue, now: Duration) -> Vec<&'static str> {
+    let mut fired = Vec::new();
+    while let Some(timer) = queue.pop_expired(now) {
+      fired.push(timer.callback);
+      queue.finish(timer, now);
+    }
+    fired
+  }
+
+  #[test]
+  fn same_deadline_fires_in_start_order() {
+    let mut queue = Queue::new();
+    queue.start(ms(20), None, "c");
+    queue.start(ms(10), None, "a");
+    queue.start(ms(10), None, "b");
+    queue.start(ms(20), None, "d");
+
+    assert_eq!(run(&mut queue, ms(5)), Vec::<&str>::new());
+    assert_eq!(run(&mut queue, ms(20)), ["a", "b", "c", "d"]);
+    assert_eq!(queue.next_deadline(), None);
+  }
+
+  #[test]
+  fn cancel_pending_and_fired() {
+    let mut queue = Queue::new();
+    let pending = queue.start(ms(10), None, "pending");
+    let fired = queue.start(ms(5), None, "fired");
+
+    assert_eq!(run(&mut queue, ms(5)), ["fired"]);
+    assert!(!queue.cancel(fired));
+    assert!(queue.cancel(pending));
+    assert!(!queue.cancel(pending));
+    assert_eq!(run(&mut queue, ms(10)), Vec::<&str>::new());
+  }
+
+  #[test]
+  fn periodic_cancelled_in_callback_is_not_rescheduled() {
+    let mut queue = Queue::new();
+    let id = queue.start(ms(10), Some(ms(10)), "periodic");
+
+    let timer = queue.pop_expired(ms(10)).unwrap();
+    assert_eq!(timer.id(), id);
+    assert!(queue.cancel(id));
+    assert!(!queue.cancel(id));
+    queue.finish(timer, ms(10));
+
+    assert_eq!(queue.next_deadline(), None);
+  }
+
+  #[test]
+  fn periodic_keeps_its_phase() {
+    let mut queue = Queue::new();
+    queue.start(ms(10), Some(ms(10)), "periodic");
+
+    assert_eq!(run(&mut queue, ms(13)), ["periodic"]);
+    assert_eq!(queue.next_deadline(), Some(ms(20)));
+  }
+
+  #[test]
+  fn periodic_behind_is_re_anchored() {
+    let mut queue = Queue::new();
+    queue.start(ms(10), Some(ms(10)), "periodic");
+
+    // Missed the calls at 20 and 30, which are skipped rather than run back
+    // to back.
+    assert_eq!(run(&mut queue, ms(35)), ["periodic"]);
+    assert_eq!(queue.next_deadline(), Some(ms(45)));
+  }
+}